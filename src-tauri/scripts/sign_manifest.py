@@ -0,0 +1,74 @@
+#!/usr/bin/env python3
+"""Signs modpack manifests for the launcher (see docs/manifest-signing.md).
+
+    sign_manifest.py keygen KEY.pem KEY.pub
+    sign_manifest.py sign KEY.pem KEY.pub manifest.json > signed.json
+
+Signatures are minisign signatures over the canonical payload: the manifest
+without `signature`, as compact JSON with sorted keys. Needs `cryptography`.
+"""
+import base64
+import hashlib
+import json
+import os
+import struct
+import sys
+
+from cryptography.hazmat.primitives import serialization
+from cryptography.hazmat.primitives.asymmetric.ed25519 import Ed25519PrivateKey
+
+
+def keygen(key_path, pub_path):
+    key = Ed25519PrivateKey.generate()
+    public = key.public_key().public_bytes(
+        serialization.Encoding.Raw, serialization.PublicFormat.Raw
+    )
+    key_id = os.urandom(8)
+    pem = key.private_bytes(
+        serialization.Encoding.PEM,
+        serialization.PrivateFormat.PKCS8,
+        serialization.NoEncryption(),
+    )
+    with open(key_path, "wb") as f:
+        f.write(pem)
+    pub = "untrusted comment: minisign public key: %016X\n%s\n" % (
+        struct.unpack("<Q", key_id)[0],
+        base64.b64encode(b"Ed" + key_id + public).decode(),
+    )
+    with open(pub_path, "w") as f:
+        f.write(pub)
+    # The value for MANIFEST_PUBKEY in src/signature.rs.
+    print(base64.b64encode(pub.encode()).decode())
+
+
+def sign(key_path, pub_path, manifest_path):
+    with open(key_path, "rb") as f:
+        key = serialization.load_pem_private_key(f.read(), None)
+    with open(pub_path) as f:
+        key_id = base64.b64decode(f.read().splitlines()[1])[2:10]
+    with open(manifest_path, encoding="utf-8") as f:
+        manifest = json.load(f)
+    manifest.pop("signature", None)
+
+    payload = json.dumps(
+        manifest, sort_keys=True, separators=(",", ":"), ensure_ascii=False
+    ).encode()
+    signature = b"ED" + key_id + key.sign(hashlib.blake2b(payload).digest())
+    trusted = "modpack manifest %s" % manifest.get("version", "")
+    global_signature = key.sign(signature[10:] + trusted.encode())
+    text = "untrusted comment: signature from sign_manifest.py\n%s\ntrusted comment: %s\n%s\n" % (
+        base64.b64encode(signature).decode(),
+        trusted,
+        base64.b64encode(global_signature).decode(),
+    )
+    manifest["signature"] = base64.b64encode(text.encode()).decode()
+    print(json.dumps(manifest, indent=2, ensure_ascii=False))
+
+
+if __name__ == "__main__":
+    if len(sys.argv) == 4 and sys.argv[1] == "keygen":
+        keygen(sys.argv[2], sys.argv[3])
+    elif len(sys.argv) == 5 and sys.argv[1] == "sign":
+        sign(sys.argv[2], sys.argv[3], sys.argv[4])
+    else:
+        sys.exit(__doc__)
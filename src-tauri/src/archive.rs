@@ -0,0 +1,77 @@
+use crate::store::copy_hashed;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ArchiveFormat {
+    Zip,
+    TarZst,
+}
+
+/// A member to pull out of an archive and the content it must have.
+pub struct ArchiveMember {
+    pub name: String,
+    pub sha1: String,
+    pub size: u64,
+    pub output: PathBuf,
+}
+
+/// Extracts `members` from `archive`, verifying each against its recorded
+/// hash. Entries the manifest does not list are ignored.
+pub fn extract_members(format: ArchiveFormat, archive: &Path, members: &[ArchiveMember]) -> Result<()> {
+    let mut wanted: HashMap<&str, &ArchiveMember> =
+        members.iter().map(|m| (m.name.as_str(), m)).collect();
+
+    match format {
+        ArchiveFormat::Zip => {
+            let mut zip = zip::ZipArchive::new(fs::File::open(archive)?)?;
+            for member in members {
+                let entry = zip
+                    .by_name(&member.name)
+                    .map_err(|_| anyhow!("{} is missing from the archive", member.name))?;
+                write_member(entry, member)?;
+                wanted.remove(member.name.as_str());
+            }
+        }
+        ArchiveFormat::TarZst => {
+            let decoder = zstd::stream::read::Decoder::new(BufReader::new(fs::File::open(archive)?))?;
+            let mut tar = tar::Archive::new(decoder);
+            for entry in tar.entries()? {
+                let entry = entry?;
+                let name = entry.path()?.to_string_lossy().replace('\\', "/");
+                let name = name.trim_start_matches("./");
+                if let Some(member) = wanted.remove(name) {
+                    write_member(entry, member)?;
+                }
+            }
+        }
+    }
+
+    if let Some(name) = wanted.keys().next() {
+        return Err(anyhow!("{} is missing from the archive", name));
+    }
+    Ok(())
+}
+
+fn write_member<R: Read>(reader: R, member: &ArchiveMember) -> Result<()> {
+    if let Some(parent) = member.output.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut file = fs::File::create(&member.output)?;
+    let (hash, size) = copy_hashed(reader, |chunk| file.write_all(chunk))?;
+    if size != member.size || !hash.eq_ignore_ascii_case(&member.sha1) {
+        return Err(anyhow!(
+            "Corrupted archive member {}: expected sha1 {}, got {}",
+            member.name,
+            member.sha1,
+            hash
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,237 @@
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+
+/// A named set of JVM flags players can pick instead of typing their own.
+#[derive(Debug, Serialize, Clone, Copy)]
+pub struct ArgsPreset {
+    pub id: &'static str,
+    pub name: &'static str,
+    pub description: &'static str,
+    #[serde(rename = "jvmArgs")]
+    pub jvm_args: &'static [&'static str],
+}
+
+pub const PRESETS: [ArgsPreset; 3] = [
+    ArgsPreset {
+        id: "aikar",
+        name: "Aikar's flags",
+        description: "G1 tuning that keeps garbage collection pauses short on large heaps",
+        jvm_args: &[
+            "-XX:+UseG1GC",
+            "-XX:+ParallelRefProcEnabled",
+            "-XX:MaxGCPauseMillis=200",
+            "-XX:+UnlockExperimentalVMOptions",
+            "-XX:+DisableExplicitGC",
+            "-XX:+AlwaysPreTouch",
+            "-XX:G1NewSizePercent=30",
+            "-XX:G1MaxNewSizePercent=40",
+            "-XX:G1HeapRegionSize=8M",
+            "-XX:G1ReservePercent=20",
+            "-XX:G1HeapWastePercent=5",
+            "-XX:G1MixedGCCountTarget=4",
+            "-XX:InitiatingHeapOccupancyPercent=15",
+            "-XX:G1MixedGCLiveThresholdPercent=90",
+            "-XX:G1RSetUpdatingPauseTimePercent=5",
+            "-XX:SurvivorRatio=32",
+            "-XX:+PerfDisableSharedMem",
+            "-XX:MaxTenuringThreshold=1",
+        ],
+    },
+    ArgsPreset {
+        id: "zgc",
+        name: "ZGC",
+        description: "Low-latency collector for machines with plenty of memory and cores",
+        jvm_args: &["-XX:+UseZGC", "-XX:+DisableExplicitGC", "-XX:+AlwaysPreTouch"],
+    },
+    ArgsPreset {
+        id: "log4j",
+        name: "Log4j hardening",
+        description: "Disables message lookups in Log4j (CVE-2021-44228)",
+        jvm_args: &["-Dlog4j2.formatMsgNoLookups=true"],
+    },
+];
+
+pub fn preset(id: &str) -> Result<&'static ArgsPreset> {
+    PRESETS
+        .iter()
+        .find(|p| p.id == id)
+        .ok_or_else(|| anyhow!("Unknown launch preset: {}", id))
+}
+
+/// JVM flags the launcher sets itself; memory comes from the memory setting.
+const RESERVED_JVM: [&str; 6] = ["-cp", "-classpath", "--class-path", "-jar", "-Xmx", "-Xms"];
+
+/// Game arguments the launcher fills in from the account and instance.
+const RESERVED_GAME: [&str; 10] = [
+    "--username",
+    "--uuid",
+    "--accessToken",
+    "--userType",
+    "--xuid",
+    "--clientId",
+    "--version",
+    "--gameDir",
+    "--assetsDir",
+    "--assetIndex",
+];
+
+/// Splits `input` like a shell would: on whitespace, honouring single and
+/// double quotes, without expanding anything. A backslash only escapes a
+/// quote, another backslash or whitespace, so Windows paths such as
+/// `C:\libs\natives` pass through unchanged.
+pub fn tokenize(input: &str) -> Result<Vec<String>> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut in_token = false;
+    let mut quote: Option<char> = None;
+    let mut chars = input.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (None | Some('"'), '\\') => {
+                let escapable = |next: char| match quote {
+                    None => next == '"' || next == '\'' || next == '\\' || next.is_whitespace(),
+                    _ => next == '"' || next == '\\',
+                };
+                match chars.next_if(|next| escapable(*next)) {
+                    Some(escaped) => current.push(escaped),
+                    None => current.push('\\'),
+                }
+                in_token = true;
+            }
+            (Some(_), c) => current.push(c),
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                in_token = true;
+            }
+            (None, c) if c.is_whitespace() => {
+                if in_token {
+                    tokens.push(std::mem::take(&mut current));
+                    in_token = false;
+                }
+            }
+            (None, c) => {
+                current.push(c);
+                in_token = true;
+            }
+        }
+    }
+
+    if let Some(q) = quote {
+        return Err(anyhow!("Unterminated {} quote in arguments", q));
+    }
+    if in_token {
+        tokens.push(current);
+    }
+    Ok(tokens)
+}
+
+pub fn validate_jvm_args(args: &[String]) -> Result<()> {
+    for arg in args {
+        check_token(arg)?;
+        if !arg.starts_with('-') {
+            return Err(anyhow!("JVM argument must start with '-': {}", arg));
+        }
+        if let Some(reserved) = RESERVED_JVM
+            .iter()
+            .find(|r| arg == *r || (r.starts_with("-X") && arg.starts_with(*r)))
+        {
+            return Err(anyhow!("{} is managed by the launcher", reserved));
+        }
+    }
+    Ok(())
+}
+
+pub fn validate_game_args(args: &[String]) -> Result<()> {
+    for arg in args {
+        check_token(arg)?;
+        let name = arg.split('=').next().unwrap_or(arg);
+        if RESERVED_GAME.contains(&name) {
+            return Err(anyhow!("{} is managed by the launcher", name));
+        }
+    }
+    Ok(())
+}
+
+fn check_token(arg: &str) -> Result<()> {
+    if arg.is_empty() || arg.contains('\0') {
+        return Err(anyhow!("Invalid argument: {:?}", arg));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn tokenize_splits_on_whitespace_and_quotes() {
+        assert_eq!(
+            tokenize("  -Xss2M\t-Dname=\"two words\" -Dsingle='a \"b\"' ").unwrap(),
+            strings(&["-Xss2M", "-Dname=two words", "-Dsingle=a \"b\""])
+        );
+        assert_eq!(tokenize("").unwrap(), Vec::<String>::new());
+        assert_eq!(tokenize("-Da=\"\"").unwrap(), strings(&["-Da="]));
+    }
+
+    #[test]
+    fn tokenize_keeps_windows_paths() {
+        assert_eq!(
+            tokenize("-Djava.library.path=C:\\libs\\natives").unwrap(),
+            strings(&["-Djava.library.path=C:\\libs\\natives"])
+        );
+        assert_eq!(
+            tokenize("\"-Dpath=C:\\Program Files\\Java\"").unwrap(),
+            strings(&["-Dpath=C:\\Program Files\\Java"])
+        );
+        assert_eq!(tokenize("C:\\dir\\").unwrap(), strings(&["C:\\dir\\"]));
+    }
+
+    #[test]
+    fn tokenize_handles_escapes() {
+        assert_eq!(tokenize("a\\ b").unwrap(), strings(&["a b"]));
+        assert_eq!(tokenize("\\\"quoted\\\"").unwrap(), strings(&["\"quoted\""]));
+        assert_eq!(tokenize("\"a\\\"b\"").unwrap(), strings(&["a\"b"]));
+        assert_eq!(tokenize("'a\\b'").unwrap(), strings(&["a\\b"]));
+    }
+
+    #[test]
+    fn tokenize_rejects_unterminated_quotes() {
+        assert!(tokenize("-Da=\"open").is_err());
+        assert!(tokenize("'open").is_err());
+    }
+
+    #[test]
+    fn presets_are_valid_and_unique() {
+        for (i, preset) in PRESETS.iter().enumerate() {
+            validate_jvm_args(&strings(preset.jvm_args)).unwrap();
+            assert!(PRESETS[..i].iter().all(|p| p.id != preset.id));
+            assert_eq!(super::preset(preset.id).unwrap().id, preset.id);
+        }
+        assert!(preset("missing").is_err());
+    }
+
+    #[test]
+    fn rejects_launcher_managed_jvm_args() {
+        for arg in ["-Xmx4G", "-Xms1G", "-cp", "-classpath", "--class-path", "-jar"] {
+            assert!(validate_jvm_args(&strings(&[arg])).is_err(), "{}", arg);
+        }
+        assert!(validate_jvm_args(&strings(&["not-a-flag"])).is_err());
+        assert!(validate_jvm_args(&strings(&["-Dnul=\0"])).is_err());
+        assert!(validate_jvm_args(&strings(&["-Xss2M", "-Dlog4j2.formatMsgNoLookups=true"])).is_ok());
+    }
+
+    #[test]
+    fn rejects_launcher_managed_game_args() {
+        for arg in RESERVED_GAME {
+            assert!(validate_game_args(&strings(&[arg])).is_err(), "{}", arg);
+        }
+        assert!(validate_game_args(&strings(&["--username=someone"])).is_err());
+        assert!(validate_game_args(&strings(&["--quickPlayMultiplayer", "mc.example.com"])).is_ok());
+    }
+}
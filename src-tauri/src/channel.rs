@@ -0,0 +1,92 @@
+use crate::meta::MetaDirectories;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+pub const DEFAULT_CHANNEL: &str = "production";
+const DEFAULT_MANIFEST_URL: &str =
+    "https://testez4.astralresources.com.br/api/v1/update/minecraft/manifest";
+
+/// Modpack channels, persisted as `channels.json` next to `settings.json`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ChannelSettings {
+    pub channel: String,
+    pub channels: BTreeMap<String, String>,
+}
+
+impl Default for ChannelSettings {
+    fn default() -> Self {
+        let mut channels = BTreeMap::new();
+        channels.insert(DEFAULT_CHANNEL.to_string(), DEFAULT_MANIFEST_URL.to_string());
+        Self {
+            channel: DEFAULT_CHANNEL.to_string(),
+            channels,
+        }
+    }
+}
+
+impl ChannelSettings {
+    pub fn load(meta_dirs: &MetaDirectories) -> Result<Self> {
+        let path = settings_path(meta_dirs);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let contents = fs::read(&path)?;
+        let mut settings: ChannelSettings = serde_json::from_slice(&contents)?;
+        settings
+            .channels
+            .entry(DEFAULT_CHANNEL.to_string())
+            .or_insert_with(|| DEFAULT_MANIFEST_URL.to_string());
+        Ok(settings)
+    }
+
+    pub fn save(&self, meta_dirs: &MetaDirectories) -> Result<()> {
+        fs::create_dir_all(&meta_dirs.base)?;
+        fs::write(settings_path(meta_dirs), serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Switches to `channel`, registering or replacing its URL when one is given.
+    pub fn select(&mut self, channel: &str, url: Option<String>) -> Result<()> {
+        let channel = channel.trim();
+        if channel.is_empty() {
+            return Err(anyhow!("Channel name cannot be empty"));
+        }
+
+        if let Some(url) = url {
+            validate_source(&url)?;
+            self.channels.insert(channel.to_string(), url);
+        } else if !self.channels.contains_key(channel) {
+            return Err(anyhow!("Unknown modpack channel: {}", channel));
+        }
+
+        self.channel = channel.to_string();
+        Ok(())
+    }
+
+    pub fn manifest_url(&self) -> Result<&str> {
+        self.channels
+            .get(&self.channel)
+            .map(String::as_str)
+            .ok_or_else(|| anyhow!("Unknown modpack channel: {}", self.channel))
+    }
+}
+
+fn settings_path(meta_dirs: &MetaDirectories) -> PathBuf {
+    meta_dirs.base.join("channels.json")
+}
+
+pub(crate) fn validate_source(url: &str) -> Result<()> {
+    let url = url.trim();
+    if url.starts_with("https://") || url.starts_with("http://") || url.starts_with("file://") {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "Manifest source must be an http(s):// or file:// URL: {}",
+            url
+        ))
+    }
+}
@@ -0,0 +1,265 @@
+use crate::channel::validate_source;
+use crate::download::{fetch_bytes, http_client};
+use crate::import::{extract_overrides, install_files, PackFile};
+use crate::instance::{new_instance_dir, InstanceConfig};
+use crate::meta::MetaDirectories;
+use crate::minecraft::get_loader_by_name;
+use crate::modpack::GameVersion;
+use crate::paths::normalize_relative;
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tauri::AppHandle;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+/// Suffix of a resolver template in the CurseForge API layout. Stripping it
+/// gives the project endpoint, whose class decides the target folder.
+const FILE_ENDPOINT: &str = "/files/{fileID}";
+
+#[derive(Deserialize)]
+struct CurseManifest {
+    minecraft: CurseMinecraft,
+    name: String,
+    files: Vec<CurseFileRef>,
+    #[serde(default = "default_overrides")]
+    overrides: String,
+}
+
+fn default_overrides() -> String {
+    "overrides".to_string()
+}
+
+#[derive(Deserialize)]
+struct CurseMinecraft {
+    version: String,
+    #[serde(rename = "modLoaders")]
+    mod_loaders: Vec<CurseModLoader>,
+}
+
+#[derive(Deserialize)]
+struct CurseModLoader {
+    id: String,
+    #[serde(default)]
+    primary: bool,
+}
+
+#[derive(Deserialize)]
+struct CurseFileRef {
+    #[serde(rename = "projectID")]
+    project_id: u64,
+    #[serde(rename = "fileID")]
+    file_id: u64,
+    #[serde(default = "default_required")]
+    required: bool,
+}
+
+fn default_required() -> bool {
+    true
+}
+
+#[derive(Deserialize)]
+struct ResolverResponse {
+    data: ResolvedFile,
+}
+
+#[derive(Deserialize)]
+struct ResolvedFile {
+    #[serde(rename = "fileName")]
+    file_name: String,
+    #[serde(rename = "downloadUrl")]
+    download_url: Option<String>,
+    #[serde(rename = "fileLength")]
+    file_length: u64,
+    hashes: Vec<ResolvedHash>,
+}
+
+#[derive(Deserialize)]
+struct ProjectResponse {
+    data: ResolvedProject,
+}
+
+#[derive(Deserialize)]
+struct ResolvedProject {
+    #[serde(rename = "classId")]
+    class_id: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct ResolvedHash {
+    value: String,
+    algo: u8,
+}
+
+const SHA1_ALGO: u8 = 1;
+
+/// Folder a CurseForge project class installs into.
+fn class_folder(class_id: u64) -> Option<&'static str> {
+    match class_id {
+        6 => Some("mods"),
+        12 => Some("resourcepacks"),
+        6552 => Some("shaderpacks"),
+        _ => None,
+    }
+}
+
+/// Imports a CurseForge modpack zip as a new instance and returns its directory.
+///
+/// `resolver` is a URL template, chosen by the player, for a CurseForge API
+/// compatible endpoint answering file lookups, with `{projectID}` and
+/// `{fileID}` substituted. It supplies the download URLs and hashes, so it has
+/// to be trusted. When it ends in `/files/{fileID}` the project endpoint is
+/// queried too and files go to the folder of their class; otherwise every
+/// file is installed into `mods/`. `file://` templates work offline.
+pub async fn import_curseforge(
+    app: AppHandle,
+    archive_path: PathBuf,
+    name: Option<String>,
+    resolver: Option<String>,
+) -> Result<PathBuf> {
+    let meta_dirs = MetaDirectories::new()?;
+    meta_dirs.ensure()?;
+
+    let mut archive = zip::ZipArchive::new(fs::File::open(&archive_path)?)?;
+    let manifest: CurseManifest = {
+        let entry = archive
+            .by_name("manifest.json")
+            .map_err(|_| anyhow!("Not a CurseForge modpack: manifest.json is missing"))?;
+        serde_json::from_reader(entry)?
+    };
+    let game = game_version(&manifest.minecraft)?;
+
+    let resolver = resolver
+        .filter(|r| !r.trim().is_empty())
+        .ok_or_else(|| anyhow!("No CurseForge resolver is configured"))?;
+    validate_source(&resolver)?;
+
+    let name = name.unwrap_or_else(|| manifest.name.clone());
+    let instance_dir = new_instance_dir(&meta_dirs, &name)?;
+
+    let result = install(&app, &meta_dirs, &mut archive, &manifest, &resolver, &instance_dir).await;
+    if let Err(e) = result {
+        let _ = fs::remove_dir_all(&instance_dir);
+        return Err(e);
+    }
+
+    InstanceConfig::new(name, game).save(&instance_dir)?;
+    Ok(instance_dir)
+}
+
+async fn install(
+    app: &AppHandle,
+    meta_dirs: &MetaDirectories,
+    archive: &mut zip::ZipArchive<fs::File>,
+    manifest: &CurseManifest,
+    resolver: &str,
+    instance_dir: &Path,
+) -> Result<()> {
+    let files = resolve_files(&manifest.files, resolver).await?;
+    install_files(app, meta_dirs, &files, instance_dir).await?;
+    extract_overrides(archive, &format!("{}/", manifest.overrides.trim_end_matches('/')), instance_dir)?;
+    Ok(())
+}
+
+async fn resolve_files(refs: &[CurseFileRef], resolver: &str) -> Result<Vec<PackFile>> {
+    let client = http_client()?;
+    let semaphore = Arc::new(Semaphore::new(8));
+    let mut set = JoinSet::new();
+
+    for file in refs.iter().filter(|f| f.required) {
+        let client = client.clone();
+        let semaphore = semaphore.clone();
+        let url = resolver
+            .replace("{projectID}", &file.project_id.to_string())
+            .replace("{fileID}", &file.file_id.to_string());
+        let project_url = resolver
+            .strip_suffix(FILE_ENDPOINT)
+            .map(|project| project.replace("{projectID}", &file.project_id.to_string()));
+        let label = format!("project {} file {}", file.project_id, file.file_id);
+        set.spawn(async move {
+            let _permit = semaphore.acquire_owned().await?;
+            let data = fetch_bytes(&client, &url)
+                .await
+                .map_err(|e| anyhow!("Failed to resolve {}: {}", label, e))?;
+            let response: ResolverResponse = serde_json::from_slice(&data)?;
+
+            let folder = match project_url {
+                Some(project_url) => {
+                    let data = fetch_bytes(&client, &project_url)
+                        .await
+                        .map_err(|e| anyhow!("Failed to resolve {}: {}", label, e))?;
+                    let project: ProjectResponse = serde_json::from_slice(&data)?;
+                    let class_id = project.data.class_id.unwrap_or(6);
+                    class_folder(class_id).ok_or_else(|| {
+                        anyhow!(
+                            "{} ({}) is of CurseForge class {}, which cannot be installed",
+                            response.data.file_name,
+                            label,
+                            class_id
+                        )
+                    })?
+                }
+                None => "mods",
+            };
+            to_pack_file(response.data, folder, &label)
+        });
+    }
+
+    let mut files = Vec::new();
+    while let Some(result) = set.join_next().await {
+        files.push(result??);
+    }
+    Ok(files)
+}
+
+fn to_pack_file(resolved: ResolvedFile, folder: &str, label: &str) -> Result<PackFile> {
+    let url = resolved.download_url.ok_or_else(|| {
+        anyhow!(
+            "{} ({}) does not allow third-party downloads",
+            resolved.file_name,
+            label
+        )
+    })?;
+    let sha1 = resolved
+        .hashes
+        .iter()
+        .find(|h| h.algo == SHA1_ALGO)
+        .ok_or_else(|| anyhow!("{} ({}) has no sha1 hash", resolved.file_name, label))?
+        .value
+        .to_ascii_lowercase();
+
+    Ok(PackFile {
+        path: normalize_relative(&format!("{}/{}", folder, resolved.file_name))?,
+        sha1,
+        size: resolved.file_length,
+        url,
+        mirrors: Vec::new(),
+    })
+}
+
+/// Maps `minecraft.modLoaders` ids such as `forge-47.2.0` onto the loaders
+/// `get_loader_by_name` understands, preferring the one marked primary.
+fn game_version(minecraft: &CurseMinecraft) -> Result<GameVersion> {
+    let mod_loader = minecraft
+        .mod_loaders
+        .iter()
+        .find(|l| l.primary)
+        .or_else(|| minecraft.mod_loaders.first())
+        .ok_or_else(|| anyhow!("Modpack does not declare a mod loader"))?;
+
+    let (loader, loader_version) = mod_loader
+        .id
+        .split_once('-')
+        .ok_or_else(|| anyhow!("Unrecognized mod loader id: {}", mod_loader.id))?;
+    let loader = loader.to_lowercase();
+    get_loader_by_name(&loader, loader_version)?;
+
+    Ok(GameVersion {
+        minecraft_version: minecraft.version.clone(),
+        loader,
+        loader_version: loader_version.to_string(),
+        jvm_args: Vec::new(),
+    })
+}
@@ -0,0 +1,70 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use tauri::AppHandle;
+use tauri::Emitter;
+
+/// Headroom kept on top of every estimate for logs, temporary files and
+/// anything the estimate missed.
+const SAFETY_MARGIN: u64 = 256 * 1024 * 1024;
+
+/// Raised when an install or update would not fit on the disk, before
+/// anything is written.
+#[derive(Debug, Serialize, Clone)]
+pub struct InsufficientSpace {
+    pub path: PathBuf,
+    #[serde(rename = "requiredBytes")]
+    pub required_bytes: u64,
+    #[serde(rename = "availableBytes")]
+    pub available_bytes: u64,
+}
+
+impl fmt::Display for InsufficientSpace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Not enough disk space on {}: {} MB required, {} MB available",
+            self.path.display(),
+            self.required_bytes.div_ceil(1024 * 1024),
+            self.available_bytes / (1024 * 1024)
+        )
+    }
+}
+
+impl std::error::Error for InsufficientSpace {}
+
+/// Fails with [`InsufficientSpace`] unless the filesystem holding `path` has
+/// room for `required` bytes plus a safety margin.
+pub fn ensure_free_space(path: &Path, required: u64) -> Result<()> {
+    if required == 0 {
+        return Ok(());
+    }
+
+    let existing = path.ancestors().find(|p| p.exists()).unwrap_or(path);
+    let available = fs4::available_space(existing)?;
+    let required = required.saturating_add(SAFETY_MARGIN);
+    if available < required {
+        return Err(InsufficientSpace {
+            path: path.to_path_buf(),
+            required_bytes: required,
+            available_bytes: available,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// [`ensure_free_space`], also reporting a shortfall to the UI as a
+/// `disk-space-insufficient` event.
+pub fn preflight(app: &AppHandle, path: &Path, required: u64) -> Result<()> {
+    let result = ensure_free_space(path, required);
+    if let Some(shortfall) = result
+        .as_ref()
+        .err()
+        .and_then(|e| e.downcast_ref::<InsufficientSpace>())
+    {
+        let _ = app.emit("disk-space-insufficient", shortfall.clone());
+    }
+    result
+}
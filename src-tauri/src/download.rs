@@ -0,0 +1,364 @@
+use anyhow::{anyhow, Result};
+use reqwest::header::{CONTENT_RANGE, RANGE};
+use reqwest::{Client, StatusCode};
+use serde::Serialize;
+use sha1::{Digest, Sha1};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+/// Longest wait for a connection to a download source.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+/// Longest gap between two reads, so a stalled source fails and the retries
+/// and mirrors get a turn.
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// HTTP client for modpack downloads and manifests, with timeouts a stalled
+/// connection cannot outlast.
+pub fn http_client() -> Result<Client> {
+    Ok(Client::builder()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .read_timeout(READ_TIMEOUT)
+        .build()?)
+}
+
+#[derive(Debug, Clone)]
+pub struct DownloadOptions {
+    pub concurrency: usize,
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    /// Where `.part` files are kept between attempts; defaults to next to the target.
+    /// Callers syncing different instances must not share a directory.
+    pub partial_dir: Option<PathBuf>,
+}
+
+impl Default for DownloadOptions {
+    fn default() -> Self {
+        Self {
+            concurrency: 8,
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(500),
+            partial_dir: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DownloadTask {
+    pub label: String,
+    pub url: String,
+    /// Fallback sources tried in order once `url` has failed.
+    pub mirrors: Vec<String>,
+    pub target: PathBuf,
+    pub size: u64,
+    pub sha1: String,
+}
+
+impl DownloadTask {
+    fn sources(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.url.as_str()).chain(self.mirrors.iter().map(String::as_str))
+    }
+}
+
+/// Which source served a file, and every source that failed before it did.
+#[derive(Debug, Serialize, Clone)]
+pub struct DownloadSource {
+    pub label: String,
+    #[serde(rename = "servedBy")]
+    pub served_by: String,
+    pub failed: Vec<FailedSource>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct FailedSource {
+    pub url: String,
+    pub error: String,
+}
+
+struct Progress<F> {
+    downloaded: AtomicU64,
+    last_reported: AtomicU64,
+    total: u64,
+    step: u64,
+    on_progress: F,
+}
+
+impl<F: Fn(&str, u64, u64)> Progress<F> {
+    fn advance(&self, label: &str, bytes: u64) {
+        let now = self.downloaded.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        let last = self.last_reported.load(Ordering::Relaxed);
+        if now >= last.saturating_add(self.step) || now >= self.total {
+            self.last_reported.store(now, Ordering::Relaxed);
+            (self.on_progress)(label, now, self.total);
+        }
+    }
+
+    fn rewind(&self, bytes: u64) {
+        self.downloaded.fetch_sub(bytes, Ordering::Relaxed);
+    }
+}
+
+/// Downloads every task with at most `options.concurrency` transfers in flight.
+///
+/// `on_progress` receives the file being transferred and the aggregated byte count
+/// across all tasks. Each attempt walks the task's sources in order before backing
+/// off. The first task that exhausts its retries aborts the rest.
+pub async fn download_all<F>(
+    client: &Client,
+    tasks: Vec<DownloadTask>,
+    options: &DownloadOptions,
+    on_progress: F,
+) -> Result<Vec<DownloadSource>>
+where
+    F: Fn(&str, u64, u64) + Send + Sync + 'static,
+{
+    let total: u64 = tasks.iter().map(|t| t.size).sum();
+    let progress = Arc::new(Progress {
+        downloaded: AtomicU64::new(0),
+        last_reported: AtomicU64::new(0),
+        total,
+        step: (total / 200).max(64 * 1024),
+        on_progress,
+    });
+    let semaphore = Arc::new(Semaphore::new(options.concurrency.max(1)));
+    let mut set = JoinSet::new();
+
+    for task in tasks {
+        let client = client.clone();
+        let options = options.clone();
+        let progress = progress.clone();
+        let semaphore = semaphore.clone();
+        set.spawn(async move {
+            let _permit = semaphore.acquire_owned().await?;
+            download_with_retry(&client, &task, &options, &progress).await
+        });
+    }
+
+    let mut sources = Vec::new();
+    while let Some(result) = set.join_next().await {
+        sources.push(result??);
+    }
+
+    Ok(sources)
+}
+
+async fn download_with_retry<F: Fn(&str, u64, u64)>(
+    client: &Client,
+    task: &DownloadTask,
+    options: &DownloadOptions,
+    progress: &Progress<F>,
+) -> Result<DownloadSource> {
+    let mut failed: Vec<FailedSource> = Vec::new();
+    let mut attempt = 0;
+    loop {
+        for url in task.sources() {
+            let mut received = 0u64;
+            match download_once(client, task, url, options, progress, &mut received).await {
+                Ok(()) => {
+                    return Ok(DownloadSource {
+                        label: task.label.clone(),
+                        served_by: url.to_string(),
+                        failed,
+                    })
+                }
+                Err(e) => {
+                    progress.rewind(received);
+                    failed.push(FailedSource {
+                        url: url.to_string(),
+                        error: e.to_string(),
+                    });
+                }
+            }
+        }
+
+        if attempt >= options.max_retries {
+            let _ = fs::remove_file(part_path(task, options));
+            let last = failed.last().map(|f| f.error.as_str()).unwrap_or_default();
+            return Err(anyhow!(
+                "Failed to download {} after {} attempts: {}",
+                task.label,
+                attempt + 1,
+                last
+            ));
+        }
+        tokio::time::sleep(options.initial_backoff * 2u32.pow(attempt)).await;
+        attempt += 1;
+    }
+}
+
+async fn download_once<F: Fn(&str, u64, u64)>(
+    client: &Client,
+    task: &DownloadTask,
+    url: &str,
+    options: &DownloadOptions,
+    progress: &Progress<F>,
+    received: &mut u64,
+) -> Result<()> {
+    if let Some(parent) = task.target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let part_path = part_path(task, options);
+    if let Some(parent) = part_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    // The .part file is kept on transport errors so the next attempt can resume it.
+    stream_to_file(client, task, url, progress, received, &part_path).await?;
+    move_into_place(&part_path, &task.target)?;
+    Ok(())
+}
+
+fn part_path(task: &DownloadTask, options: &DownloadOptions) -> PathBuf {
+    match &options.partial_dir {
+        Some(dir) => dir.join(format!("{}.part", task.sha1)),
+        None => temp_path_for(&task.target, "part"),
+    }
+}
+
+/// Streams the task into `part_path`, hashing as it goes, and checks the result
+/// against the expected size and sha1 before it is allowed into the instance.
+///
+/// An existing partial file is resumed with a Range request, whichever source it
+/// came from; servers that answer with the full body instead of
+/// `206 Partial Content` get a fresh download.
+async fn stream_to_file<F: Fn(&str, u64, u64)>(
+    client: &Client,
+    task: &DownloadTask,
+    url: &str,
+    progress: &Progress<F>,
+    received: &mut u64,
+    part_path: &Path,
+) -> Result<()> {
+    let mut hasher = Sha1::new();
+
+    let file = if url.starts_with("file://") {
+        let bytes = fetch_bytes(client, url).await?;
+        let mut file = fs::File::create(part_path)?;
+        file.write_all(&bytes)?;
+        hasher.update(&bytes);
+        *received = bytes.len() as u64;
+        progress.advance(&task.label, *received);
+        file
+    } else {
+        let existing = match fs::metadata(part_path) {
+            Ok(meta) if meta.len() < task.size => meta.len(),
+            _ => 0,
+        };
+
+        let mut request = client.get(url);
+        if existing > 0 {
+            request = request.header(RANGE, format!("bytes={}-", existing));
+        }
+        let mut response = request.send().await?;
+
+        if response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+            let _ = fs::remove_file(part_path);
+            return Err(anyhow!("Server rejected resume for {}", task.label));
+        }
+
+        let resumed = existing > 0 && response.status() == StatusCode::PARTIAL_CONTENT;
+        if resumed && content_range_start(&response) != Some(existing) {
+            let _ = fs::remove_file(part_path);
+            return Err(anyhow!("Server resumed {} at the wrong offset", task.label));
+        }
+
+        let mut file = if resumed {
+            std::io::copy(&mut fs::File::open(part_path)?, &mut hasher)?;
+            *received = existing;
+            progress.advance(&task.label, existing);
+            fs::OpenOptions::new().append(true).open(part_path)?
+        } else {
+            response = response.error_for_status()?;
+            fs::File::create(part_path)?
+        };
+
+        while let Some(chunk) = response.chunk().await? {
+            file.write_all(&chunk)?;
+            hasher.update(&chunk);
+            *received += chunk.len() as u64;
+            progress.advance(&task.label, chunk.len() as u64);
+        }
+        file
+    };
+    file.sync_all()?;
+
+    if *received != task.size {
+        let _ = fs::remove_file(part_path);
+        return Err(anyhow!(
+            "Corrupted download {}: expected {} bytes, received {}",
+            task.label,
+            task.size,
+            received
+        ));
+    }
+
+    let hash = format!("{:x}", hasher.finalize());
+    if !hash.eq_ignore_ascii_case(&task.sha1) {
+        let _ = fs::remove_file(part_path);
+        return Err(anyhow!(
+            "Corrupted download {}: expected sha1 {}, got {}",
+            task.label,
+            task.sha1,
+            hash
+        ));
+    }
+
+    Ok(())
+}
+
+/// The first byte of a `206` response, from `Content-Range: bytes <start>-<end>/<total>`.
+fn content_range_start(response: &reqwest::Response) -> Option<u64> {
+    response
+        .headers()
+        .get(CONTENT_RANGE)?
+        .to_str()
+        .ok()?
+        .strip_prefix("bytes ")?
+        .split('-')
+        .next()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// Renames `from` onto `target`, copying through a sibling temp file when the
+/// two live on different filesystems so the final swap is still a rename.
+pub(crate) fn move_into_place(from: &Path, target: &Path) -> std::io::Result<()> {
+    if fs::rename(from, target).is_ok() {
+        return Ok(());
+    }
+
+    let temp_path = temp_path_for(target, "download");
+    fs::copy(from, &temp_path)?;
+    fs::rename(&temp_path, target)?;
+    fs::remove_file(from)
+}
+
+fn temp_path_for(target: &Path, extension: &str) -> PathBuf {
+    let mut name = target.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(extension);
+    target.with_file_name(name)
+}
+
+/// Fetches `url` over HTTP, or reads it from disk for `file://` sources used in testing.
+pub async fn fetch_bytes(client: &Client, url: &str) -> Result<Vec<u8>> {
+    if let Some(path) = url.strip_prefix("file://") {
+        return fs::read(path).map_err(|e| anyhow!("Failed to read {}: {}", url, e));
+    }
+
+    let bytes = client
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?;
+    Ok(bytes.to_vec())
+}
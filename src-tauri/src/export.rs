@@ -0,0 +1,210 @@
+use crate::instance::Instance;
+use crate::meta::MetaDirectories;
+use crate::modpack::{FileEntry, GameVersion, Manifest};
+use crate::paths::normalize_relative;
+use crate::signature::signing_payload;
+use crate::store::copy_hashed;
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use sha2::{Digest, Sha512};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Instance folders exported when the caller does not list its own.
+pub const DEFAULT_INCLUDE: [&str; 7] = [
+    "mods",
+    "config",
+    "defaultconfigs",
+    "kubejs",
+    "resourcepacks",
+    "shaderpacks",
+    "scripts",
+];
+
+pub struct ExportOptions {
+    pub name: String,
+    pub version: String,
+    /// Download URL for each file; `{path}` and `{sha1}` are substituted.
+    pub url_template: String,
+    pub include: Vec<String>,
+    pub output: PathBuf,
+    pub mrpack_output: Option<PathBuf>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExportReport {
+    pub files: u64,
+    #[serde(rename = "totalBytes")]
+    pub total_bytes: u64,
+}
+
+struct ExportedFile {
+    path: String,
+    sha1: String,
+    sha512: String,
+    size: u64,
+    url: String,
+}
+
+/// Exports `instance` (or the default instance) with the game version it is set up for.
+pub fn export_modpack(instance: Option<&str>, options: &ExportOptions) -> Result<ExportReport> {
+    let meta_dirs = MetaDirectories::new()?;
+    let instance = Instance::resolve(&meta_dirs, instance)?;
+    export_instance(&instance.dir, instance.config.game, options)
+}
+
+/// Hashes the instance and writes a manifest for it, plus a `.mrpack` when asked.
+///
+/// The manifest is written in the canonical form signatures are computed over,
+/// so it can be signed as-is before the `signature` field is added.
+pub fn export_instance(
+    instance_dir: &Path,
+    game: GameVersion,
+    options: &ExportOptions,
+) -> Result<ExportReport> {
+    if !options.url_template.contains("{path}") && !options.url_template.contains("{sha1}") {
+        return Err(anyhow!("URL template must contain {{path}} or {{sha1}}"));
+    }
+
+    let mut relative_paths = Vec::new();
+    for dir in &options.include {
+        let dir = normalize_relative(dir).map_err(|e| anyhow!("Invalid export path {}: {}", dir, e))?;
+        let root = instance_dir.join(&dir);
+        if root.is_dir() {
+            walk(&root, &dir, &mut relative_paths)?;
+        } else if root.is_file() {
+            relative_paths.push(dir);
+        }
+    }
+    relative_paths.sort();
+    relative_paths.dedup();
+
+    let mut files = Vec::new();
+    for path in relative_paths {
+        let (sha1, sha512, size) = hash_file(&instance_dir.join(&path))?;
+        let url = options
+            .url_template
+            .replace("{path}", &encode_path(&path))
+            .replace("{sha1}", &sha1);
+        files.push(ExportedFile {
+            path,
+            sha1,
+            sha512,
+            size,
+            url,
+        });
+    }
+
+    let manifest = Manifest {
+        version: options.version.clone(),
+        game: game.clone(),
+        files: files
+            .iter()
+            .map(|f| FileEntry::new(f.path.clone(), f.sha1.clone(), f.size, f.url.clone()))
+            .collect(),
+        mirrors: Vec::new(),
+        signature: None,
+    };
+    if let Some(parent) = options.output.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&options.output, signing_payload(serde_json::to_value(&manifest)?)?)?;
+
+    if let Some(mrpack_output) = &options.mrpack_output {
+        write_mrpack(mrpack_output, &game, options, &files)?;
+    }
+
+    Ok(ExportReport {
+        files: files.len() as u64,
+        total_bytes: files.iter().map(|f| f.size).sum(),
+    })
+}
+
+fn walk(dir: &Path, relative: &str, out: &mut Vec<String>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        let child = format!("{}/{}", relative.trim_end_matches('/'), name);
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            walk(&entry.path(), &child, out)?;
+        } else if file_type.is_file() {
+            out.push(child);
+        }
+    }
+    Ok(())
+}
+
+fn hash_file(path: &Path) -> Result<(String, String, u64)> {
+    let mut sha512 = Sha512::new();
+    let (sha1, size) = copy_hashed(fs::File::open(path)?, |chunk| {
+        sha512.update(chunk);
+        Ok(())
+    })?;
+    Ok((sha1, format!("{:x}", sha512.finalize()), size))
+}
+
+/// Percent-encodes each path segment, keeping `/` between them.
+pub(crate) fn encode_path(path: &str) -> String {
+    path.split('/')
+        .map(|segment| {
+            segment
+                .bytes()
+                .map(|b| match b {
+                    b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                        (b as char).to_string()
+                    }
+                    _ => format!("%{:02X}", b),
+                })
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn write_mrpack(
+    output: &Path,
+    game: &GameVersion,
+    options: &ExportOptions,
+    files: &[ExportedFile],
+) -> Result<()> {
+    let loader_key = match game.loader.to_lowercase().as_str() {
+        "forge" => "forge",
+        "neoforge" => "neoforge",
+        "fabric" => "fabric-loader",
+        "quilt" => "quilt-loader",
+        other => return Err(anyhow!("Unsupported mod loader: {}", other)),
+    };
+
+    let mut dependencies = HashMap::new();
+    dependencies.insert("minecraft", game.minecraft_version.clone());
+    dependencies.insert(loader_key, game.loader_version.clone());
+
+    let index = serde_json::json!({
+        "formatVersion": 1,
+        "game": "minecraft",
+        "versionId": options.version,
+        "name": options.name,
+        "files": files.iter().map(|f| serde_json::json!({
+            "path": f.path,
+            "hashes": { "sha1": f.sha1, "sha512": f.sha512 },
+            "downloads": [f.url],
+            "fileSize": f.size,
+        })).collect::<Vec<_>>(),
+        "dependencies": dependencies,
+    });
+
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut zip = zip::ZipWriter::new(fs::File::create(output)?);
+    zip.start_file(
+        "modrinth.index.json",
+        zip::write::SimpleFileOptions::default(),
+    )?;
+    zip.write_all(&serde_json::to_vec_pretty(&index)?)?;
+    zip.finish()?;
+    Ok(())
+}
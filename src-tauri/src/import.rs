@@ -0,0 +1,112 @@
+use crate::download::{download_all, http_client, DownloadOptions, DownloadTask};
+use crate::lock::StoreLock;
+use crate::meta::MetaDirectories;
+use crate::paths::safe_join;
+use crate::store::FileStore;
+use anyhow::Result;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use tauri::AppHandle;
+use tauri::Emitter;
+
+/// A file an imported modpack pulls from the network into its instance.
+pub struct PackFile {
+    pub path: String,
+    pub sha1: String,
+    pub size: u64,
+    pub url: String,
+    pub mirrors: Vec<String>,
+}
+
+/// Downloads `files` through the shared store and links them into the instance.
+pub async fn install_files(
+    app: &AppHandle,
+    meta_dirs: &MetaDirectories,
+    files: &[PackFile],
+    instance_dir: &Path,
+) -> Result<()> {
+    let _store_lock = StoreLock::shared(meta_dirs)?;
+    let store = FileStore::new(&meta_dirs.caches);
+    let mut tasks = Vec::new();
+    let mut queued = HashSet::new();
+    for file in files {
+        if !store.has_valid(&file.sha1, file.size)? && queued.insert(file.sha1.clone()) {
+            tasks.push(DownloadTask {
+                label: file.path.clone(),
+                url: file.url.clone(),
+                mirrors: file.mirrors.clone(),
+                target: store.blob_path(&file.sha1)?,
+                size: file.size,
+                sha1: file.sha1.clone(),
+            });
+        }
+    }
+
+    if !tasks.is_empty() {
+        let on_progress = {
+            let app = app.clone();
+            move |path: &str, current: u64, total: u64| {
+                let percentage = if total > 0 {
+                    (current as f64 / total as f64 * 100.0) as f32
+                } else {
+                    100.0
+                };
+                let _ = app.emit(
+                    "minecraft-progress",
+                    serde_json::json!({
+                        "message": format!("Downloading {}", path),
+                        "percentage": percentage.clamp(0.0, 100.0),
+                        "component": "downloading",
+                        "current": current,
+                        "total": total,
+                    }),
+                );
+            }
+        };
+        let options = DownloadOptions {
+            partial_dir: Some(
+                meta_dirs
+                    .caches
+                    .join("instances")
+                    .join(instance_dir.file_name().unwrap_or_default())
+                    .join("downloads"),
+            ),
+            ..Default::default()
+        };
+        download_all(&http_client()?, tasks, &options, on_progress).await?;
+    }
+
+    for file in files {
+        store.install_into(&file.sha1, &file.path, &safe_join(instance_dir, &file.path)?)?;
+    }
+    Ok(())
+}
+
+/// Copies every archive entry under `prefix` into the instance, in order, so
+/// a later prefix (e.g. `client-overrides/`) wins on conflicts.
+pub fn extract_overrides<R: std::io::Read + std::io::Seek>(
+    archive: &mut zip::ZipArchive<R>,
+    prefix: &str,
+    instance_dir: &Path,
+) -> Result<()> {
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        let Some(relative) = entry.name().strip_prefix(prefix).map(str::to_string) else {
+            continue;
+        };
+        if entry.is_dir() || relative.is_empty() {
+            continue;
+        }
+
+        let target = safe_join(instance_dir, &relative)?;
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        if target.exists() {
+            fs::remove_file(&target)?;
+        }
+        std::io::copy(&mut entry, &mut fs::File::create(&target)?)?;
+    }
+    Ok(())
+}
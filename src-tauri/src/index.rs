@@ -0,0 +1,104 @@
+use crate::store::sha1_file;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct IndexEntry {
+    size: u64,
+    mtime: u64,
+    sha1: String,
+}
+
+/// Cached hashes of installed files, so update checks only rehash files whose
+/// size or modification time changed since they were last seen.
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct FileIndex {
+    entries: HashMap<String, IndexEntry>,
+}
+
+impl FileIndex {
+    /// Loads the index, starting empty if it is missing or unreadable.
+    pub fn load(path: &Path) -> Self {
+        fs::read(path)
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_vec(self)?)?;
+        Ok(())
+    }
+
+    /// Returns the sha1 of `path`, or `None` if it does not exist. The cached
+    /// hash is reused unless `force` is set or the file's metadata changed.
+    pub fn hash(&mut self, relative: &str, path: &Path, force: bool) -> Result<Option<String>> {
+        let Some((size, mtime)) = stat(path) else {
+            self.entries.remove(relative);
+            return Ok(None);
+        };
+
+        if !force {
+            if let Some(entry) = self.entries.get(relative) {
+                if entry.size == size && entry.mtime == mtime {
+                    return Ok(Some(entry.sha1.clone()));
+                }
+            }
+        }
+
+        let sha1 = sha1_file(path)?;
+        self.entries.insert(
+            relative.to_string(),
+            IndexEntry {
+                size,
+                mtime,
+                sha1: sha1.clone(),
+            },
+        );
+        Ok(Some(sha1))
+    }
+
+    /// Records a file whose content is already known, e.g. one just installed.
+    pub fn record(&mut self, relative: &str, path: &Path, sha1: &str) {
+        match stat(path) {
+            Some((size, mtime)) => {
+                self.entries.insert(
+                    relative.to_string(),
+                    IndexEntry {
+                        size,
+                        mtime,
+                        sha1: sha1.to_ascii_lowercase(),
+                    },
+                );
+            }
+            None => {
+                self.entries.remove(relative);
+            }
+        }
+    }
+
+    pub fn forget(&mut self, relative: &str) {
+        self.entries.remove(relative);
+    }
+}
+
+fn stat(path: &Path) -> Option<(u64, u64)> {
+    let meta = fs::metadata(path).ok()?;
+    if !meta.is_file() {
+        return None;
+    }
+    let mtime = meta
+        .modified()
+        .ok()?
+        .duration_since(UNIX_EPOCH)
+        .ok()?
+        .as_nanos() as u64;
+    Some((meta.len(), mtime))
+}
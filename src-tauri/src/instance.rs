@@ -0,0 +1,367 @@
+use crate::args::validate_jvm_args;
+use crate::channel::validate_source;
+use crate::lock::{InstanceLock, Operation};
+use crate::meta::MetaDirectories;
+use crate::modpack::{installed_game_version, GameVersion};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Directory name of the instance the launcher has always shipped with.
+pub const DEFAULT_INSTANCE: &str = "MetaInstance";
+
+/// Description of an instance, stored as `instance.json` in its directory.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct InstanceConfig {
+    pub name: String,
+    #[serde(flatten)]
+    pub game: GameVersion,
+    /// Manifest the instance is synced from; `None` for imported or hand-made packs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<ManifestSource>,
+    #[serde(default)]
+    pub settings: InstanceSettings,
+}
+
+/// Where a synced instance gets its modpack manifest.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum ManifestSource {
+    /// Follows the channel selected in the launcher.
+    Channel,
+    /// A fixed manifest URL.
+    Url { url: String },
+}
+
+/// Launch settings that take precedence over the launcher-wide ones. Unset
+/// fields fall back to the global settings.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct InstanceSettings {
+    /// Memory for the game in GB.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allocated_ram: Option<f32>,
+    /// Appended after the modpack's own JVM arguments.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub jvm_args: Vec<String>,
+    /// Java executable to run the game with, in place of the runtime the
+    /// launcher downloads for the game version.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub java_path: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub window_width: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub window_height: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fullscreen: Option<bool>,
+    /// Extra environment variables for the game process.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+}
+
+impl InstanceSettings {
+    pub fn validate(&self) -> Result<()> {
+        if let Some(ram) = self.allocated_ram {
+            if !ram.is_finite() || ram < 1.0 {
+                return Err(anyhow!("Allocated memory must be at least 1 GB"));
+            }
+        }
+        if self.window_width == Some(0) || self.window_height == Some(0) {
+            return Err(anyhow!("Window size must be greater than zero"));
+        }
+        validate_jvm_args(&self.jvm_args)?;
+        if let Some(java) = &self.java_path {
+            if !java.is_file() {
+                return Err(anyhow!("Java executable not found: {}", java.display()));
+            }
+        }
+        for (key, value) in &self.env {
+            if key.is_empty() || key.contains(['=', '\0']) || value.contains('\0') {
+                return Err(anyhow!("Invalid environment variable: {:?}", key));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl InstanceConfig {
+    pub fn new(name: String, game: GameVersion) -> Self {
+        Self {
+            name,
+            game,
+            source: None,
+            settings: InstanceSettings::default(),
+        }
+    }
+
+    pub fn load(dir: &Path) -> Result<Self> {
+        let data = fs::read(dir.join("instance.json"))?;
+        Ok(serde_json::from_slice(&data)?)
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<()> {
+        fs::create_dir_all(dir)?;
+        fs::write(dir.join("instance.json"), serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+/// An instance on disk, identified by its directory name.
+#[derive(Debug, Serialize, Clone)]
+pub struct Instance {
+    pub id: String,
+    #[serde(skip)]
+    pub dir: PathBuf,
+    #[serde(flatten)]
+    pub config: InstanceConfig,
+}
+
+impl Instance {
+    /// Looks up `id`, or the default instance when none is given. The default
+    /// instance works without an `instance.json` and then follows the
+    /// selected channel with whatever game version is installed.
+    pub fn resolve(meta_dirs: &MetaDirectories, id: Option<&str>) -> Result<Self> {
+        let id = id.unwrap_or(DEFAULT_INSTANCE);
+        if instance_dir_name(id)? != id {
+            return Err(anyhow!("Invalid instance id: {:?}", id));
+        }
+
+        let dir = meta_dirs.instances.join(id);
+        let config = if dir.join("instance.json").exists() {
+            InstanceConfig::load(&dir)?
+        } else if id == DEFAULT_INSTANCE {
+            InstanceConfig {
+                source: Some(ManifestSource::Channel),
+                ..InstanceConfig::new(DEFAULT_INSTANCE.to_string(), installed_game_version(meta_dirs)?)
+            }
+        } else {
+            return Err(anyhow!("Instance not found: {}", id));
+        };
+
+        Ok(Self {
+            id: id.to_string(),
+            dir,
+            config,
+        })
+    }
+
+    pub fn is_default(&self) -> bool {
+        self.id == DEFAULT_INSTANCE
+    }
+
+    /// The installed modpack manifest. The default instance keeps it in `meta`
+    /// where it lived before instances could be added.
+    pub fn manifest_path(&self, meta_dirs: &MetaDirectories) -> PathBuf {
+        self.state_dir(meta_dirs).join("manifest.json")
+    }
+
+    pub fn index_path(&self, meta_dirs: &MetaDirectories) -> PathBuf {
+        self.state_dir(meta_dirs).join("index.json")
+    }
+
+    /// Scratch space for staged updates, kept apart so instances can sync side by side.
+    pub fn cache_dir(&self, meta_dirs: &MetaDirectories) -> PathBuf {
+        if self.is_default() {
+            meta_dirs.caches.clone()
+        } else {
+            meta_dirs.caches.join("instances").join(&self.id)
+        }
+    }
+
+    fn state_dir(&self, meta_dirs: &MetaDirectories) -> PathBuf {
+        if self.is_default() {
+            meta_dirs.meta.clone()
+        } else {
+            self.dir.join(".launcher")
+        }
+    }
+}
+
+/// Result of [`list_instances`]; an unreadable instance is reported on its
+/// own instead of hiding every other one.
+#[derive(Debug, Serialize, Default)]
+pub struct InstanceList {
+    pub instances: Vec<Instance>,
+    pub broken: Vec<BrokenInstance>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct BrokenInstance {
+    pub id: String,
+    pub error: String,
+}
+
+/// Every instance with an `instance.json`, plus the default one, sorted by name.
+pub fn list_instances(meta_dirs: &MetaDirectories) -> Result<InstanceList> {
+    let mut ids = vec![DEFAULT_INSTANCE.to_string()];
+    if meta_dirs.instances.exists() {
+        for entry in fs::read_dir(&meta_dirs.instances)? {
+            let entry = entry?;
+            let id = entry.file_name().to_string_lossy().to_string();
+            if id != DEFAULT_INSTANCE && entry.path().join("instance.json").exists() {
+                ids.push(id);
+            }
+        }
+    }
+
+    let mut list = InstanceList::default();
+    for id in ids {
+        match Instance::resolve(meta_dirs, Some(&id)) {
+            Ok(instance) => list.instances.push(instance),
+            Err(e) => list.broken.push(BrokenInstance {
+                id,
+                error: e.to_string(),
+            }),
+        }
+    }
+
+    list.instances.sort_by_key(|i| i.config.name.to_lowercase());
+    list.broken.sort_by(|a, b| a.id.cmp(&b.id));
+    Ok(list)
+}
+
+pub fn create_instance(
+    meta_dirs: &MetaDirectories,
+    name: &str,
+    game: GameVersion,
+    source: Option<ManifestSource>,
+) -> Result<Instance> {
+    if let Some(ManifestSource::Url { url }) = &source {
+        validate_source(url)?;
+    }
+
+    let dir = new_instance_dir(meta_dirs, name)?;
+    for sub in ["mods", "config", "saves"] {
+        fs::create_dir_all(dir.join(sub))?;
+    }
+    let config = InstanceConfig {
+        source,
+        ..InstanceConfig::new(name.trim().to_string(), game)
+    };
+    config.save(&dir)?;
+    Instance::resolve(meta_dirs, Some(&instance_dir_name(name)?))
+}
+
+/// Renames an instance, moving its directory along. The default instance only
+/// changes its display name, since its directory is what makes it the default.
+pub fn rename_instance(meta_dirs: &MetaDirectories, id: &str, name: &str) -> Result<Instance> {
+    let _lock = InstanceLock::acquire(meta_dirs, id, Operation::Manage)?;
+    let mut instance = Instance::resolve(meta_dirs, Some(id))?;
+    let new_id = instance_dir_name(name)?;
+    if !instance.is_default() && new_id != instance.id {
+        let _new_lock = InstanceLock::acquire(meta_dirs, &new_id, Operation::Manage)?;
+        let dir = new_instance_dir(meta_dirs, name)?;
+        fs::rename(&instance.dir, &dir)?;
+        let old_cache = instance.cache_dir(meta_dirs);
+        if old_cache.exists() {
+            let _ = fs::remove_dir_all(old_cache);
+        }
+        instance.id = new_id;
+        instance.dir = dir;
+    }
+
+    instance.config.name = name.trim().to_string();
+    instance.config.save(&instance.dir)?;
+    Ok(instance)
+}
+
+pub fn update_instance_settings(
+    meta_dirs: &MetaDirectories,
+    id: &str,
+    settings: InstanceSettings,
+) -> Result<Instance> {
+    settings.validate()?;
+    let _lock = InstanceLock::acquire(meta_dirs, id, Operation::Manage)?;
+    let mut instance = Instance::resolve(meta_dirs, Some(id))?;
+    instance.config.settings = settings;
+    instance.config.save(&instance.dir)?;
+    Ok(instance)
+}
+
+pub fn delete_instance(meta_dirs: &MetaDirectories, id: &str) -> Result<()> {
+    let _lock = InstanceLock::acquire(meta_dirs, id, Operation::Manage)?;
+    let instance = Instance::resolve(meta_dirs, Some(id))?;
+    if instance.is_default() {
+        return Err(anyhow!("The default instance cannot be deleted"));
+    }
+
+    fs::remove_dir_all(&instance.dir)?;
+    let cache = instance.cache_dir(meta_dirs);
+    if cache.exists() {
+        fs::remove_dir_all(cache)?;
+    }
+    Ok(())
+}
+
+/// Copies an instance, worlds and settings included, under a new name.
+pub fn duplicate_instance(meta_dirs: &MetaDirectories, id: &str, name: &str) -> Result<Instance> {
+    let _lock = InstanceLock::acquire(meta_dirs, id, Operation::Manage)?;
+    let source = Instance::resolve(meta_dirs, Some(id))?;
+    let _copy_lock = InstanceLock::acquire(meta_dirs, &instance_dir_name(name)?, Operation::Manage)?;
+    let dir = new_instance_dir(meta_dirs, name)?;
+    if let Err(e) = copy_dir(&source.dir, &dir) {
+        let _ = fs::remove_dir_all(&dir);
+        return Err(e);
+    }
+
+    let copy = Instance {
+        id: instance_dir_name(name)?,
+        dir,
+        config: InstanceConfig {
+            name: name.trim().to_string(),
+            ..source.config.clone()
+        },
+    };
+    let manifest = source.manifest_path(meta_dirs);
+    if source.is_default() && manifest.exists() {
+        let target = copy.manifest_path(meta_dirs);
+        fs::create_dir_all(target.parent().unwrap_or(&copy.dir))?;
+        fs::copy(manifest, target)?;
+    }
+    copy.config.save(&copy.dir)?;
+    Ok(copy)
+}
+
+fn copy_dir(from: &Path, to: &Path) -> Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Picks the directory for a new instance, refusing to reuse an existing one.
+pub fn new_instance_dir(meta_dirs: &MetaDirectories, name: &str) -> Result<PathBuf> {
+    let instance_dir = meta_dirs.instances.join(instance_dir_name(name)?);
+    if instance_dir.exists() {
+        return Err(anyhow!("Instance already exists: {}", name));
+    }
+    Ok(instance_dir)
+}
+
+/// Turns a display name into a directory name that is safe on every platform.
+pub fn instance_dir_name(name: &str) -> Result<String> {
+    let sanitized: String = name
+        .trim()
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    let sanitized = sanitized.trim_matches(|c: char| c == '.' || c == ' ').to_string();
+
+    if sanitized.is_empty() {
+        return Err(anyhow!("Invalid instance name: {:?}", name));
+    }
+    Ok(sanitized)
+}
@@ -1,11 +1,32 @@
 use serde::{Deserialize, Serialize};
+use crate::channel::ChannelSettings;
+use crate::instance::{Instance, InstanceList};
 use crate::meta::MetaDirectories;
 use std::path::PathBuf;
 use tauri::Emitter;
 
+pub mod archive;
+pub mod args;
+pub mod channel;
+pub mod curseforge;
+pub mod disk;
+pub mod download;
+pub mod export;
+pub mod import;
+pub mod index;
+pub mod instance;
+pub mod lock;
 pub mod meta;
 pub mod minecraft;
 pub mod modpack;
+pub mod mrpack;
+pub mod optional;
+pub mod patch;
+pub mod paths;
+pub mod process;
+pub mod signature;
+pub mod staging;
+pub mod store;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct UserSettings {
@@ -18,6 +39,14 @@ pub struct UserSettings {
     pub microsoft_account: Option<MicrosoftAccount>,
     #[serde(rename = "clientToken")]
     pub client_token: Option<String>,
+    /// Extra JVM flags, written as on a command line.
+    #[serde(rename = "jvmArgs", default)]
+    pub jvm_args: String,
+    #[serde(rename = "gameArgs", default)]
+    pub game_args: String,
+    /// Id of a built-in preset from `args::PRESETS`, applied before `jvm_args`.
+    #[serde(rename = "jvmPreset", default)]
+    pub jvm_preset: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -86,30 +115,225 @@ async fn create_microsoft_auth_link() -> Result<String, String> {
 }
 
 #[tauri::command]
-async fn check_manifest_update() -> Result<bool, bool> {
-    match modpack::modpack_required_update().await {
+async fn check_manifest_update(instance: Option<String>) -> Result<bool, bool> {
+    match modpack::modpack_required_update(instance.as_deref()).await {
         Ok(v) => Ok(v),
         Err(_) => Err(false),
     }
 }
 
 #[tauri::command]
-async fn update_modpack(app: tauri::AppHandle) -> Result<(), String> {
-    match crate::modpack::download_modpack(app.clone()).await {
-        Ok(_) => Ok(()),
+async fn get_manifest_channels() -> Result<ChannelSettings, String> {
+    let meta_dirs = MetaDirectories::new().map_err(|e| e.to_string())?;
+    ChannelSettings::load(&meta_dirs).map_err(|e| format!("Failed to load modpack channels: {}", e))
+}
+
+#[tauri::command]
+async fn set_manifest_channel(channel: String, url: Option<String>) -> Result<ChannelSettings, String> {
+    let meta_dirs = MetaDirectories::new().map_err(|e| e.to_string())?;
+    let mut settings = ChannelSettings::load(&meta_dirs)
+        .map_err(|e| format!("Failed to load modpack channels: {}", e))?;
+    settings
+        .select(&channel, url)
+        .map_err(|e| format!("Failed to switch modpack channel: {}", e))?;
+    settings
+        .save(&meta_dirs)
+        .map_err(|e| format!("Failed to save modpack channels: {}", e))?;
+    Ok(settings)
+}
+
+#[tauri::command]
+async fn update_modpack(
+    app: tauri::AppHandle,
+    instance: Option<String>,
+    concurrency: Option<usize>,
+) -> Result<crate::modpack::SyncReport, String> {
+    let mut options = crate::download::DownloadOptions::default();
+    if let Some(concurrency) = concurrency {
+        options.concurrency = concurrency;
+    }
+
+    match crate::modpack::download_modpack(app.clone(), instance.as_deref(), options, false).await {
+        Ok(report) => Ok(report),
         Err(e) => Err(format!("Failed to launch modpack: {}", e)),
     }
 }
 
 #[tauri::command]
-async fn launch_meta(app: tauri::AppHandle, settings: UserSettings) -> Result<(), String> {
+async fn plan_modpack_update(instance: Option<String>) -> Result<crate::modpack::UpdatePlan, String> {
+    crate::modpack::plan_modpack_update(instance.as_deref())
+        .await
+        .map_err(|e| format!("Failed to plan modpack update: {}", e))
+}
+
+#[tauri::command]
+async fn verify_modpack(
+    app: tauri::AppHandle,
+    instance: Option<String>,
+) -> Result<crate::modpack::SyncReport, String> {
+    let options = crate::download::DownloadOptions::default();
+    match crate::modpack::download_modpack(app.clone(), instance.as_deref(), options, true).await {
+        Ok(report) => Ok(report),
+        Err(e) => Err(format!("Failed to verify modpack: {}", e)),
+    }
+}
+
+#[tauri::command]
+async fn get_optional_mods(
+    instance: Option<String>,
+) -> Result<Vec<crate::optional::OptionalMod>, String> {
+    crate::modpack::list_optional_mods(instance.as_deref())
+        .await
+        .map_err(|e| format!("Failed to load optional mods: {}", e))
+}
+
+#[tauri::command]
+async fn set_optional_mod(
+    instance: Option<String>,
+    path: String,
+    enabled: bool,
+) -> Result<Vec<crate::optional::OptionalMod>, String> {
+    crate::modpack::set_optional_mod(instance.as_deref(), &path, enabled)
+        .await
+        .map_err(|e| format!("Failed to update optional mod: {}", e))
+}
+
+#[tauri::command]
+async fn collect_store_garbage() -> Result<crate::store::GcReport, String> {
+    crate::modpack::collect_store_garbage().map_err(|e| format!("Failed to clean file store: {}", e))
+}
+
+#[tauri::command]
+async fn import_mrpack(
+    app: tauri::AppHandle,
+    path: String,
+    name: Option<String>,
+) -> Result<PathBuf, String> {
+    crate::mrpack::import_mrpack(app, path.into(), name)
+        .await
+        .map_err(|e| format!("Failed to import Modrinth modpack: {}", e))
+}
+
+#[tauri::command]
+async fn import_curseforge(
+    app: tauri::AppHandle,
+    path: String,
+    name: Option<String>,
+    resolver: Option<String>,
+) -> Result<PathBuf, String> {
+    crate::curseforge::import_curseforge(app, path.into(), name, resolver)
+        .await
+        .map_err(|e| format!("Failed to import CurseForge modpack: {}", e))
+}
+
+#[tauri::command]
+async fn export_modpack(
+    instance: Option<String>,
+    name: String,
+    version: String,
+    url_template: String,
+    output: String,
+    mrpack_output: Option<String>,
+    include: Option<Vec<String>>,
+) -> Result<crate::export::ExportReport, String> {
+    let options = crate::export::ExportOptions {
+        name,
+        version,
+        url_template,
+        include: include.unwrap_or_else(|| {
+            crate::export::DEFAULT_INCLUDE.iter().map(|d| d.to_string()).collect()
+        }),
+        output: output.into(),
+        mrpack_output: mrpack_output.map(PathBuf::from),
+    };
+
+    crate::export::export_modpack(instance.as_deref(), &options)
+        .map_err(|e| format!("Failed to export modpack: {}", e))
+}
+
+#[tauri::command]
+async fn list_instances() -> Result<InstanceList, String> {
+    let meta_dirs = MetaDirectories::new().map_err(|e| e.to_string())?;
+    crate::instance::list_instances(&meta_dirs).map_err(|e| format!("Failed to list instances: {}", e))
+}
+
+#[tauri::command]
+async fn create_instance(
+    name: String,
+    game: Option<crate::modpack::GameVersion>,
+    source: Option<crate::instance::ManifestSource>,
+) -> Result<Instance, String> {
+    let meta_dirs = MetaDirectories::new().map_err(|e| e.to_string())?;
+    crate::instance::create_instance(&meta_dirs, &name, game.unwrap_or_default(), source)
+        .map_err(|e| format!("Failed to create instance: {}", e))
+}
+
+#[tauri::command]
+async fn rename_instance(id: String, name: String) -> Result<Instance, String> {
+    let meta_dirs = MetaDirectories::new().map_err(|e| e.to_string())?;
+    crate::instance::rename_instance(&meta_dirs, &id, &name)
+        .map_err(|e| format!("Failed to rename instance: {}", e))
+}
+
+#[tauri::command]
+async fn set_instance_settings(
+    id: String,
+    settings: crate::instance::InstanceSettings,
+) -> Result<Instance, String> {
+    let meta_dirs = MetaDirectories::new().map_err(|e| e.to_string())?;
+    crate::instance::update_instance_settings(&meta_dirs, &id, settings)
+        .map_err(|e| format!("Failed to save instance settings: {}", e))
+}
+
+#[tauri::command]
+async fn get_jvm_presets() -> Vec<crate::args::ArgsPreset> {
+    crate::args::PRESETS.to_vec()
+}
+
+#[tauri::command]
+async fn delete_instance(id: String) -> Result<(), String> {
+    let meta_dirs = MetaDirectories::new().map_err(|e| e.to_string())?;
+    crate::instance::delete_instance(&meta_dirs, &id)
+        .map_err(|e| format!("Failed to delete instance: {}", e))
+}
+
+#[tauri::command]
+async fn duplicate_instance(id: String, name: String) -> Result<Instance, String> {
+    let meta_dirs = MetaDirectories::new().map_err(|e| e.to_string())?;
+    crate::instance::duplicate_instance(&meta_dirs, &id, &name)
+        .map_err(|e| format!("Failed to duplicate instance: {}", e))
+}
+
+#[tauri::command]
+async fn launch_meta(
+    app: tauri::AppHandle,
+    settings: UserSettings,
+    instance: Option<String>,
+) -> Result<(), String> {
 
-    match crate::minecraft::launch_minecraft_with_forge(settings, app.clone()).await {
+    match crate::minecraft::launch_minecraft_with_forge(settings, app.clone(), instance).await {
         Ok(_) => Ok(()),
         Err(e) => Err(format!("Failed to launch modpack: {}", e)),
     }
 }
 
+#[tauri::command]
+async fn stop_minecraft(
+    app: tauri::AppHandle,
+    instance: Option<String>,
+    timeout_secs: Option<u64>,
+) -> Result<bool, String> {
+    let timeout = std::time::Duration::from_secs(timeout_secs.unwrap_or(10));
+    crate::minecraft::stop_minecraft(&app, instance.as_deref(), timeout)
+        .await
+        .map_err(|e| format!("Failed to stop Minecraft: {}", e))
+}
+
+#[tauri::command]
+async fn is_minecraft_running(instance: Option<String>) -> Result<bool, String> {
+    Ok(crate::minecraft::is_minecraft_running(instance.as_deref()))
+}
+
 #[tauri::command]
 async fn authenticate_microsoft(code: String) -> Result<MicrosoftAccount, String> {
     let client = reqwest::Client::new();
@@ -305,8 +529,27 @@ pub fn run() {
             extract_code_from_redirect_url,
             open_microsoft_auth_modal,
             launch_meta,
+            stop_minecraft,
+            is_minecraft_running,
             check_manifest_update,
             update_modpack,
+            verify_modpack,
+            plan_modpack_update,
+            get_manifest_channels,
+            set_manifest_channel,
+            get_optional_mods,
+            set_optional_mod,
+            collect_store_garbage,
+            import_mrpack,
+            import_curseforge,
+            export_modpack,
+            list_instances,
+            create_instance,
+            rename_instance,
+            set_instance_settings,
+            get_jvm_presets,
+            delete_instance,
+            duplicate_instance,
             get_root_dir,
             set_root_dir
         ])
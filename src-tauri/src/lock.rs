@@ -0,0 +1,174 @@
+use crate::instance::instance_dir_name;
+use crate::meta::MetaDirectories;
+use anyhow::{anyhow, Result};
+use fs4::fs_std::FileExt;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// What an instance is locked for.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Operation {
+    Launch,
+    Update,
+    /// Renaming, duplicating, deleting or reconfiguring the instance.
+    Manage,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Operation::Launch => "running",
+            Operation::Update => "being updated",
+            Operation::Manage => "being modified",
+        })
+    }
+}
+
+/// Raised when an instance is already in use by this launcher or another one.
+#[derive(Debug, Serialize, Clone)]
+pub struct InstanceBusy {
+    pub instance: String,
+    /// The conflicting operation, when it is known.
+    pub operation: Option<Operation>,
+}
+
+impl fmt::Display for InstanceBusy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.operation {
+            Some(operation) => write!(f, "Instance {} is already {}", self.instance, operation),
+            None => write!(f, "Instance {} is in use by another launcher window", self.instance),
+        }
+    }
+}
+
+impl std::error::Error for InstanceBusy {}
+
+/// Instances locked by this process, so a second request is refused without
+/// touching the lockfile.
+static HELD: Lazy<Mutex<HashMap<String, Operation>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Exclusive use of an instance, held until dropped. Other launcher processes
+/// are kept out by an OS lock on `meta/locks/<id>.lock`, which is released
+/// even if the launcher crashes.
+#[derive(Debug)]
+pub struct InstanceLock {
+    id: String,
+    file: File,
+}
+
+impl InstanceLock {
+    pub fn acquire(meta_dirs: &MetaDirectories, id: &str, operation: Operation) -> Result<Self> {
+        if instance_dir_name(id)? != id {
+            return Err(anyhow!("Invalid instance id: {:?}", id));
+        }
+        {
+            let mut held = HELD.lock().unwrap();
+            if let Some(current) = held.get(id) {
+                return Err(InstanceBusy {
+                    instance: id.to_string(),
+                    operation: Some(*current),
+                }
+                .into());
+            }
+            held.insert(id.to_string(), operation);
+        }
+
+        match lock_file(meta_dirs, id, operation) {
+            Ok(file) => Ok(Self {
+                id: id.to_string(),
+                file,
+            }),
+            Err(e) => {
+                HELD.lock().unwrap().remove(id);
+                Err(e)
+            }
+        }
+    }
+}
+
+impl Drop for InstanceLock {
+    fn drop(&mut self) {
+        let _ = FileExt::unlock(&self.file);
+        HELD.lock().unwrap().remove(&self.id);
+    }
+}
+
+/// Hold on the shared file store. Syncs and imports keep a shared hold while
+/// blobs they added are not yet referenced by any manifest; garbage collection
+/// needs it exclusively, so it never deletes those blobs from under them.
+#[derive(Debug)]
+pub struct StoreLock {
+    file: File,
+}
+
+impl StoreLock {
+    pub fn shared(meta_dirs: &MetaDirectories) -> Result<Self> {
+        let file = store_lock_file(meta_dirs)?;
+        if !FileExt::try_lock_shared(&file)? {
+            return Err(anyhow!("The file store is being cleaned up, try again shortly"));
+        }
+        Ok(Self { file })
+    }
+
+    pub fn exclusive(meta_dirs: &MetaDirectories) -> Result<Self> {
+        let file = store_lock_file(meta_dirs)?;
+        if !FileExt::try_lock_exclusive(&file)? {
+            return Err(anyhow!("The file store cannot be cleaned while an update is running"));
+        }
+        Ok(Self { file })
+    }
+}
+
+impl Drop for StoreLock {
+    fn drop(&mut self) {
+        let _ = FileExt::unlock(&self.file);
+    }
+}
+
+fn store_lock_file(meta_dirs: &MetaDirectories) -> Result<File> {
+    fs::create_dir_all(&meta_dirs.caches)?;
+    Ok(OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(meta_dirs.caches.join("store.lock"))?)
+}
+
+fn lock_file(meta_dirs: &MetaDirectories, id: &str, operation: Operation) -> Result<File> {
+    let path = lock_path(meta_dirs, id);
+    fs::create_dir_all(path.parent().unwrap_or(&meta_dirs.meta))?;
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&path)?;
+
+    if !FileExt::try_lock_exclusive(&file)? {
+        // Windows keeps locked files unreadable, so the holder may be unknown.
+        let operation = fs::read_to_string(&path)
+            .ok()
+            .and_then(|s| serde_json::from_str(s.trim()).ok());
+        return Err(InstanceBusy {
+            instance: id.to_string(),
+            operation,
+        }
+        .into());
+    }
+
+    file.set_len(0)?;
+    file.write_all(serde_json::to_string(&operation)?.as_bytes())?;
+    Ok(file)
+}
+
+fn lock_path(meta_dirs: &MetaDirectories, id: &str) -> PathBuf {
+    meta_dirs.meta.join("locks").join(format!("{}.lock", id))
+}
@@ -1,29 +1,103 @@
-use crate::{meta::MetaDirectories, UserSettings};
+use crate::{args, disk::preflight, process::game_command, instance::{Instance, DEFAULT_INSTANCE}, lock::{InstanceLock, Operation}, meta::MetaDirectories, UserSettings};
 use anyhow::{anyhow, Result};
 use lyceris::auth::AuthMethod;
+use lyceris::http::fetch::fetch;
+use lyceris::json::version::asset_index::AssetIndex;
+use lyceris::json::version::manifest::VersionManifest;
+use lyceris::json::version::meta::vanilla::VersionMeta;
 use lyceris::minecraft::loader::fabric::Fabric;
 use lyceris::minecraft::loader::forge::Forge;
 use lyceris::minecraft::loader::neoforge::NeoForge;
 use lyceris::minecraft::loader::quilt::Quilt;
 use lyceris::minecraft::loader::Loader;
 use lyceris::minecraft::{
-    config::{ConfigBuilder, Memory, Profile},
+    config::{Config, ConfigBuilder, Memory, Profile},
     emitter::{Emitter as LycerisEmitter, Event},
     install::install,
-    launch::launch,
+    VERSION_MANIFEST_ENDPOINT,
 };
 use once_cell::sync::Lazy;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::process::Stdio;
 use std::sync::Arc;
+use std::time::Duration;
 use tauri::AppHandle;
 use tauri::Emitter;
-use tokio::sync::Mutex as AsyncMutex;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::sync::{watch, Notify};
 
-pub static RUNNING_PROCS: Lazy<
-    std::sync::Mutex<HashMap<String, Arc<AsyncMutex<tokio::process::Child>>>>,
-> = Lazy::new(|| std::sync::Mutex::new(HashMap::new()));
+/// A game process owned by the task waiting for it to exit.
+#[derive(Clone)]
+pub struct RunningGame {
+    pid: Option<u32>,
+    /// Asks the waiting task to kill the process outright.
+    kill: Arc<Notify>,
+    exited: watch::Receiver<bool>,
+}
+
+pub static RUNNING_PROCS: Lazy<std::sync::Mutex<HashMap<String, RunningGame>>> =
+    Lazy::new(|| std::sync::Mutex::new(HashMap::new()));
+
+/// How long `stop_minecraft` waits for the process to die after killing it.
+const KILL_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Launch options once an instance's overrides are applied over the global settings.
+struct LaunchOptions {
+    memory_gb: f32,
+    jvm_args: Vec<String>,
+    game_args: Vec<String>,
+    runtime_dir: PathBuf,
+    /// Overrides the Java lyceris installs into `runtime_dir`.
+    java_path: Option<PathBuf>,
+    env: BTreeMap<String, String>,
+}
+
+impl LaunchOptions {
+    /// JVM flags go modpack, preset, global, instance, so later ones win when
+    /// the JVM sees the same flag twice.
+    fn merge(settings: &UserSettings, instance: &Instance, meta_dirs: &MetaDirectories) -> Result<Self> {
+        let overrides = &instance.config.settings;
+
+        let custom_jvm = args::tokenize(&settings.jvm_args)?;
+        args::validate_jvm_args(&custom_jvm)?;
+        let custom_game = args::tokenize(&settings.game_args)?;
+        args::validate_game_args(&custom_game)?;
+
+        let mut jvm_args = instance.config.game.jvm_args.clone();
+        if let Some(id) = settings.jvm_preset.as_deref().filter(|id| !id.is_empty()) {
+            jvm_args.extend(args::preset(id)?.jvm_args.iter().map(|arg| arg.to_string()));
+        }
+        jvm_args.extend(custom_jvm);
+        jvm_args.extend(overrides.jvm_args.iter().cloned());
+
+        let mut game_args = custom_game;
+        if let Some(width) = overrides.window_width {
+            game_args.extend(["--width".to_string(), width.to_string()]);
+        }
+        if let Some(height) = overrides.window_height {
+            game_args.extend(["--height".to_string(), height.to_string()]);
+        }
+        if overrides.fullscreen == Some(true) {
+            game_args.push("--fullscreen".to_string());
+        }
+
+        Ok(Self {
+            memory_gb: overrides.allocated_ram.unwrap_or(settings.allocated_ram).max(1.0),
+            jvm_args,
+            game_args,
+            runtime_dir: meta_dirs.java_versions.join("default").join("bin"),
+            java_path: overrides.java_path.clone(),
+            env: overrides.env.clone(),
+        })
+    }
+}
 
-pub async fn launch_minecraft_with_forge(settings: UserSettings, app: AppHandle) -> Result<()> {
+pub async fn launch_minecraft_with_forge(
+    settings: UserSettings,
+    app: AppHandle,
+    instance: Option<String>,
+) -> Result<()> {
     let _ = app.emit("logs", serde_json::json!({
         "type": "launcher",
         "message": "[Launcher:Rust] minecraft.rs Launch function called",
@@ -32,7 +106,10 @@ pub async fn launch_minecraft_with_forge(settings: UserSettings, app: AppHandle)
     let meta_dirs = MetaDirectories::new()?;
     meta_dirs.ensure()?;
 
-    let instance_dir = meta_dirs.default_instance.clone();
+    let instance = Instance::resolve(&meta_dirs, instance.as_deref())?;
+    // Held until the game exits, so the instance is not updated underneath it.
+    let lock = InstanceLock::acquire(&meta_dirs, &instance.id, Operation::Launch)?;
+    let instance_dir = instance.dir.clone();
     let _ = app.emit("logs", serde_json::json!({
         "type": "launcher",
         "message": format!("[Launcher:Rust] Launch Minecraft found instance {}", instance_dir.display()),
@@ -74,13 +151,22 @@ pub async fn launch_minecraft_with_forge(settings: UserSettings, app: AppHandle)
         }));
     }
 
-    let memory_gb = settings.allocated_ram.max(1.0);
+    let options = LaunchOptions::merge(&settings, &instance, &meta_dirs)?;
+    let _ = app.emit("logs", serde_json::json!({
+        "type": "launcher",
+        "message": format!("[Launcher:Rust] Memory GB {}", options.memory_gb),
+    }));
+
+    let game = instance.config.game.clone();
     let _ = app.emit("logs", serde_json::json!({
         "type": "launcher",
-        "message": format!("[Launcher:Rust] Memory GB {}", memory_gb),
+        "message": format!(
+            "[Launcher:Rust] Minecraft {} with {} {}",
+            game.minecraft_version, game.loader, game.loader_version
+        ),
     }));
 
-    let loader = get_loader_by_name("forge", "47.4.0")?;
+    let loader = get_loader_by_name(&game.loader, &game.loader_version)?;
 
     let _ = app.emit("logs", serde_json::json!({
         "type": "launcher",
@@ -92,20 +178,34 @@ pub async fn launch_minecraft_with_forge(settings: UserSettings, app: AppHandle)
         "message": format!("[Launcher:Rust] Instance Path {}", instance_dir.display()),
     }));
 
-    let java_path = meta_dirs.java_versions.join("default").join("bin");
     let _ = app.emit("logs", serde_json::json!({
         "type": "launcher",
-        "message": format!("[Launcher:Rust] Java Path {}", java_path.display()),
+        "message": format!(
+            "[Launcher:Rust] Java Path {}",
+            options.java_path.as_ref().unwrap_or(&options.runtime_dir).display()
+        ),
     }));
 
-    let config = ConfigBuilder::new(meta_dirs.meta.clone(), "1.20.1".to_string(), auth_method)
+    let config = ConfigBuilder::new(meta_dirs.meta.clone(), game.minecraft_version.clone(), auth_method)
         .profile(Profile::new("".to_string(), instance_dir.clone()))
-        .runtime_dir(java_path)
-        .memory(Memory::Gigabyte(memory_gb as u16))
+        .runtime_dir(options.runtime_dir.clone())
+        .memory(Memory::Gigabyte(options.memory_gb as u16))
+        .custom_java_args(options.jvm_args.clone())
+        .custom_args(options.game_args.clone())
         .loader(loader)
         .build();
 
     // Install
+    match install_requirement(&config).await {
+        Ok(required) => preflight(&app, &meta_dirs.root_dir, required)?,
+        Err(e) => {
+            let _ = app.emit("logs", serde_json::json!({
+                "type": "launcher",
+                "message": format!("[Launcher:Rust] Skipping disk space check: {}", e),
+            }));
+        }
+    }
+
     let _ = app.emit("logs", serde_json::json!({
         "type": "launcher",
         "message": "[Launcher:Rust] Before Minecraft Installing function..."
@@ -123,24 +223,42 @@ pub async fn launch_minecraft_with_forge(settings: UserSettings, app: AppHandle)
         "type": "launcher",
         "message": "[Launcher:Rust] Launching Minecraft process..."
     }));
-    let child = match launch(&config, Some(&emitter)).await {
+    let spawned = async {
+        let mut command = game_command(&config, options.java_path.as_deref()).await?;
+        command.envs(&options.env).stdout(Stdio::piped());
+        Ok::<_, anyhow::Error>(command.spawn()?)
+    };
+    let mut child = match spawned.await {
         Ok(c) => c,
         Err(e) => {
             let _ = app.emit("logs", serde_json::json!({
                 "type": "launcher",
                 "message": format!("[Launcher:Rust][ERROR] Failed to launch: {}", e),
             }));
-            return Err(e.into());
+            return Err(e);
         }
     };
-    let child_arc = Arc::new(AsyncMutex::new(child));
-
-    RUNNING_PROCS
-        .lock()
-        .unwrap()
-        .insert("minecraft".to_string(), child_arc.clone());
+    if let Some(stdout) = child.stdout.take() {
+        let emitter = emitter.clone();
+        tokio::spawn(async move {
+            let mut lines = BufReader::new(stdout).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                emitter.emit(Event::Console, line).await;
+            }
+        });
+    }
+    let kill = Arc::new(Notify::new());
+    let (exited_tx, exited) = watch::channel(false);
+    RUNNING_PROCS.lock().unwrap().insert(
+        instance.id.clone(),
+        RunningGame {
+            pid: child.id(),
+            kill: kill.clone(),
+            exited,
+        },
+    );
 
-    let _ = app.emit("minecraft-started", "started");
+    let _ = app.emit("minecraft-started", serde_json::json!({ "instance": instance.id }));
     let _ = app.emit("logs", serde_json::json!({
         "type": "launcher",
         "message": "[Launcher:Rust] Minecraft started!"
@@ -148,16 +266,25 @@ pub async fn launch_minecraft_with_forge(settings: UserSettings, app: AppHandle)
 
     {
         let app_clone = app.clone();
+        let instance_id = instance.id.clone();
         tokio::spawn(async move {
-            let mut guard = child_arc.lock().await;
-            if let Err(e) = guard.wait().await {
+            let _lock = lock;
+            let status = tokio::select! {
+                status = child.wait() => status,
+                _ = kill.notified() => {
+                    let _ = child.start_kill();
+                    child.wait().await
+                }
+            };
+            if let Err(e) = status {
                 let _ = app_clone.emit("logs", serde_json::json!({
                     "type": "launcher",
                     "message": format!("[Launcher:Rust][ERROR] Minecraft process exited with error: {}", e),
                 }));
             }
-            RUNNING_PROCS.lock().unwrap().remove("minecraft");
-            let _ = app_clone.emit("minecraft-exited", "exited");
+            RUNNING_PROCS.lock().unwrap().remove(&instance_id);
+            let _ = exited_tx.send(true);
+            let _ = app_clone.emit("minecraft-exited", serde_json::json!({ "instance": instance_id }));
             let _ = app_clone.emit("logs", serde_json::json!({
                 "type": "launcher",
                 "message": "[Launcher:Rust] Minecraft process exited"
@@ -168,7 +295,139 @@ pub async fn launch_minecraft_with_forge(settings: UserSettings, app: AppHandle)
     Ok(())
 }
 
+pub fn is_minecraft_running(instance: Option<&str>) -> bool {
+    RUNNING_PROCS
+        .lock()
+        .unwrap()
+        .contains_key(instance.unwrap_or(DEFAULT_INSTANCE))
+}
+
+/// Asks the game to close and kills it if it is still running after
+/// `timeout`. Returns whether it had to be killed.
+pub async fn stop_minecraft(app: &AppHandle, instance: Option<&str>, timeout: Duration) -> Result<bool> {
+    let id = instance.unwrap_or(DEFAULT_INSTANCE);
+    let game = RUNNING_PROCS
+        .lock()
+        .unwrap()
+        .get(id)
+        .cloned()
+        .ok_or_else(|| anyhow!("Minecraft is not running for instance {}", id))?;
+    let mut exited = game.exited.clone();
+
+    let _ = app.emit("minecraft-stopping", serde_json::json!({ "instance": id }));
+    let _ = app.emit("logs", serde_json::json!({
+        "type": "launcher",
+        "message": format!("[Launcher:Rust] Stopping Minecraft for instance {}", id),
+    }));
+
+    if let Some(pid) = game.pid {
+        if let Err(e) = request_exit(pid).await {
+            let _ = app.emit("logs", serde_json::json!({
+                "type": "launcher",
+                "message": format!("[Launcher:Rust] Could not ask Minecraft to close: {}", e),
+            }));
+        }
+        if tokio::time::timeout(timeout, exited.wait_for(|done| *done)).await.is_ok() {
+            return Ok(false);
+        }
+    }
+
+    let _ = app.emit("minecraft-killed", serde_json::json!({ "instance": id }));
+    let _ = app.emit("logs", serde_json::json!({
+        "type": "launcher",
+        "message": "[Launcher:Rust] Minecraft did not close in time, killing it",
+    }));
+    game.kill.notify_one();
+    let _ = tokio::time::timeout(KILL_TIMEOUT, exited.wait_for(|done| *done))
+        .await
+        .map_err(|_| anyhow!("Minecraft did not exit after being killed"))?;
+    Ok(true)
+}
+
+/// Sends the polite close request: SIGTERM on Unix, a window close on Windows.
+async fn request_exit(pid: u32) -> Result<()> {
+    let (program, args) = if cfg!(windows) {
+        ("taskkill", ["/PID", &pid.to_string()])
+    } else {
+        ("kill", ["-TERM", &pid.to_string()])
+    };
+    let status = tokio::process::Command::new(program).args(args).status().await?;
+    if !status.success() {
+        return Err(anyhow!("{} exited with {}", program, status));
+    }
+    Ok(())
+}
+
+/// Rough upper bound for a loader's own libraries, which are only known
+/// once its installer has run.
+const LOADER_ALLOWANCE: u64 = 128 * 1024 * 1024;
+/// Rough size of an unpacked Java runtime.
+const JAVA_RUNTIME_ALLOWANCE: u64 = 256 * 1024 * 1024;
+
+/// Estimates the bytes `install` still has to write for `config`, from the
+/// version metadata and whatever of it is already on disk.
+async fn install_requirement<T: Loader>(config: &Config<T>) -> Result<u64> {
+    let client = reqwest::Client::new();
+    let mut required = 0;
+
+    let version_json_path = config.get_version_json_path();
+    let meta: VersionMeta = if version_json_path.exists() {
+        serde_json::from_slice(&std::fs::read(&version_json_path)?)?
+    } else {
+        let manifest: VersionManifest = fetch(VERSION_MANIFEST_ENDPOINT, Some(&client)).await?;
+        let url = &manifest
+            .versions
+            .iter()
+            .find(|v| v.id == config.version)
+            .ok_or_else(|| anyhow!("Unknown Minecraft version {}", config.version))?
+            .url;
+        if config.loader.is_some() {
+            required += LOADER_ALLOWANCE;
+        }
+        fetch(url, Some(&client)).await?
+    };
+
+    if !config.get_version_jar_path().exists() {
+        required += meta.downloads.client.size.max(0) as u64;
+    }
+
+    let libraries = config.get_libraries_path();
+    for library in &meta.libraries {
+        let Some(artifact) = library.downloads.as_ref().and_then(|d| d.artifact.as_ref()) else {
+            continue;
+        };
+        if artifact.path.as_ref().is_some_and(|path| !libraries.join(path).exists()) {
+            required += artifact.size.max(0) as u64;
+        }
+    }
+
+    let index_path = config
+        .get_indexes_path()
+        .join(format!("{}.json", meta.asset_index.id));
+    let asset_index: AssetIndex = if index_path.exists() {
+        serde_json::from_slice(&std::fs::read(&index_path)?)?
+    } else {
+        fetch(&meta.asset_index.url, Some(&client)).await?
+    };
+    let objects = config.get_assets_path().join("objects");
+    required += asset_index
+        .objects
+        .values()
+        .filter(|o| o.hash.len() > 2 && !objects.join(&o.hash[..2]).join(&o.hash).exists())
+        .map(|o| o.size)
+        .sum::<u64>();
 
+    let component = meta
+        .java_version
+        .as_ref()
+        .map(|j| j.component.clone())
+        .unwrap_or_default();
+    if !config.get_runtime_path().join(component).exists() {
+        required += JAVA_RUNTIME_ALLOWANCE;
+    }
+
+    Ok(required)
+}
 
 async fn get_auth_method_with_validation(
     settings: &UserSettings,
@@ -268,7 +527,7 @@ where
     emitter
 }
 
-fn get_loader_by_name(name: &str, loader_version: &str) -> Result<Box<dyn Loader>> {
+pub(crate) fn get_loader_by_name(name: &str, loader_version: &str) -> Result<Box<dyn Loader>> {
     match name.to_lowercase().as_str() {
         "fabric" => Ok(Fabric(loader_version.to_string()).into()),
         "forge" => Ok(Forge(loader_version.to_string()).into()),
@@ -1,70 +1,670 @@
+use crate::archive::{extract_members, ArchiveFormat, ArchiveMember};
+use crate::channel::ChannelSettings;
+use crate::disk::preflight;
+use crate::download::{
+    download_all, fetch_bytes, http_client, DownloadOptions, DownloadSource, DownloadTask,
+};
+use crate::export::encode_path;
+use crate::index::FileIndex;
+use crate::instance::{list_instances, Instance, ManifestSource};
+use crate::lock::{InstanceLock, Operation, StoreLock};
 use crate::meta::MetaDirectories;
+use crate::optional::{OptionalMod, OptionalSelection};
+use crate::patch::{apply_patches, PatchFormat, PatchJob};
+use crate::paths::normalize_relative;
+use crate::signature::verify_manifest;
+use crate::staging::StagedUpdate;
+use crate::store::{is_shareable, FileStore, GcReport};
+use anyhow::anyhow;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
-use sha1::{Digest, Sha1};
+use std::collections::{HashMap, HashSet};
 use std::fs;
+use std::path::Path;
 use tauri::AppHandle;
 use tauri::Emitter;
 
 #[derive(Deserialize, Serialize)]
-struct Manifest {
-    version: String,
-    files: Vec<FileEntry>,
+pub(crate) struct Manifest {
+    pub(crate) version: String,
+    #[serde(flatten)]
+    pub(crate) game: GameVersion,
+    pub(crate) files: Vec<FileEntry>,
+    /// URL templates tried after each entry's own sources; `{path}` and `{sha1}` are substituted.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) mirrors: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) signature: Option<String>,
+}
+
+/// Game and loader a modpack runs on. Manifests that predate these fields
+/// default to the Forge 1.20.1 setup the launcher originally shipped with.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct GameVersion {
+    #[serde(default = "default_minecraft_version")]
+    pub minecraft_version: String,
+    #[serde(default = "default_loader")]
+    pub loader: String,
+    #[serde(default = "default_loader_version")]
+    pub loader_version: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub jvm_args: Vec<String>,
+}
+
+impl Default for GameVersion {
+    fn default() -> Self {
+        Self {
+            minecraft_version: default_minecraft_version(),
+            loader: default_loader(),
+            loader_version: default_loader_version(),
+            jvm_args: Vec::new(),
+        }
+    }
+}
+
+fn default_minecraft_version() -> String {
+    "1.20.1".to_string()
+}
+
+fn default_loader() -> String {
+    "forge".to_string()
+}
+
+fn default_loader_version() -> String {
+    "47.4.0".to_string()
 }
 
 #[derive(Deserialize, Serialize)]
-struct FileEntry {
+pub(crate) struct FileEntry {
+    pub(crate) path: String,
+    pub(crate) sha1: String,
+    pub(crate) size: u64,
+    pub(crate) url: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) mirrors: Vec<String>,
+    #[serde(default)]
+    pub(crate) policy: FilePolicy,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) patches: Vec<PatchEntry>,
+    /// Makes this entry an archive extracted into the directory at `path`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) archive: Option<ArchiveSpec>,
+    /// Lets players leave this entry out; see [`OptionalSelection`].
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) optional: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) group: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) description: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+pub(crate) struct ArchiveSpec {
+    pub(crate) format: ArchiveFormat,
+    pub(crate) files: Vec<ArchiveFile>,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+pub(crate) struct ArchiveFile {
+    pub(crate) path: String,
+    pub(crate) sha1: String,
+    pub(crate) size: u64,
+}
+
+/// A file a manifest places in the instance; archive entries expand to one per member.
+struct InstalledFile<'a> {
     path: String,
-    sha1: String,
-    size: u64,
-    url: String,
+    sha1: &'a str,
+    policy: FilePolicy,
 }
 
-pub async fn modpack_required_update() -> anyhow::Result<bool> {
-    let meta_dirs = MetaDirectories::new()?;
+/// Optional delta from an earlier version of a file, tried before a full download.
+#[derive(Deserialize, Serialize, Clone)]
+pub(crate) struct PatchEntry {
+    pub(crate) from_sha1: String,
+    pub(crate) url: String,
+    pub(crate) sha1: String,
+    pub(crate) size: u64,
+    #[serde(default)]
+    pub(crate) format: PatchFormat,
+}
+
+impl FileEntry {
+    pub(crate) fn new(path: String, sha1: String, size: u64, url: String) -> Self {
+        Self {
+            path,
+            sha1,
+            size,
+            url,
+            mirrors: Vec::new(),
+            policy: FilePolicy::default(),
+            patches: Vec::new(),
+            archive: None,
+            optional: false,
+            group: None,
+            description: None,
+        }
+    }
+}
+
+impl Manifest {
+    /// Deserializes a manifest and normalizes every entry path, rejecting the
+    /// manifest if any of them could resolve outside the instance.
+    fn parse(data: &[u8]) -> anyhow::Result<Self> {
+        let mut manifest: Manifest = serde_json::from_slice(data)?;
+        for file in &mut manifest.files {
+            file.path = normalize_relative(&file.path)?;
+            if let Some(archive) = &mut file.archive {
+                for member in &mut archive.files {
+                    member.path = normalize_relative(&member.path)?;
+                }
+            }
+        }
+
+        let mut seen = HashSet::new();
+        for installed in manifest.installed_files() {
+            if !seen.insert(installed.path.to_lowercase()) {
+                return Err(anyhow!("Duplicate path in manifest: {}", installed.path));
+            }
+        }
+        Ok(manifest)
+    }
+
+    fn installed_files(&self) -> Vec<InstalledFile<'_>> {
+        expand_installed(self.files.iter())
+    }
+
+    /// Entries to install, leaving out optional ones the player deselected.
+    fn selected_files<'a>(
+        &'a self,
+        selection: &'a OptionalSelection,
+    ) -> impl Iterator<Item = &'a FileEntry> + Clone + 'a {
+        self.files
+            .iter()
+            .filter(|f| !f.optional || selection.is_enabled(&f.path))
+    }
+
+    /// Fallback URLs for `file`: its own mirrors, then the manifest-wide templates.
+    fn mirrors_for(&self, file: &FileEntry) -> Vec<String> {
+        let templated = self.mirrors.iter().map(|template| {
+            template
+                .replace("{path}", &encode_path(&file.path))
+                .replace("{sha1}", &file.sha1)
+        });
+        file.mirrors.iter().cloned().chain(templated).collect()
+    }
+
+    fn optional_mods(&self, selection: &OptionalSelection) -> Vec<OptionalMod> {
+        self.files
+            .iter()
+            .filter(|f| f.optional)
+            .map(|f| OptionalMod {
+                path: f.path.clone(),
+                group: f.group.clone(),
+                description: f.description.clone(),
+                size: f.size,
+                enabled: selection.is_enabled(&f.path),
+            })
+            .collect()
+    }
+}
+
+fn expand_installed<'a>(files: impl Iterator<Item = &'a FileEntry>) -> Vec<InstalledFile<'a>> {
+    let mut installed = Vec::new();
+    for file in files {
+        match &file.archive {
+            Some(archive) => installed.extend(archive.files.iter().map(|member| InstalledFile {
+                path: format!("{}/{}", file.path, member.path),
+                sha1: &member.sha1,
+                policy: file.policy,
+            })),
+            None => installed.push(InstalledFile {
+                path: file.path.clone(),
+                sha1: &file.sha1,
+                policy: file.policy,
+            }),
+        }
+    }
+    installed
+}
+
+/// How a sync treats a file that already exists locally with different content.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum FilePolicy {
+    /// Always replaced with the manifest version.
+    #[default]
+    Overwrite,
+    /// Updated only while it still matches the previously installed version.
+    KeepIfModified,
+    /// Installed when missing, never touched afterwards.
+    CreateOnly,
+}
+
+#[derive(Debug, Serialize, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum PreserveReason {
+    Modified,
+    AlreadyExists,
+    RemovedFromPack,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PreservedFile {
+    pub path: String,
+    pub reason: PreserveReason,
+}
+
+/// Files a sync deliberately left alone because of their policy, and downloads
+/// that only succeeded after falling back from their primary source.
+#[derive(Debug, Serialize, Default)]
+pub struct SyncReport {
+    pub preserved: Vec<PreservedFile>,
+    pub fallbacks: Vec<DownloadSource>,
+}
+
+async fn fetch_remote_manifest(
+    client: &Client,
+    meta_dirs: &MetaDirectories,
+    instance: &Instance,
+) -> anyhow::Result<Manifest> {
+    let url = match &instance.config.source {
+        Some(ManifestSource::Channel) => ChannelSettings::load(meta_dirs)?.manifest_url()?.to_string(),
+        Some(ManifestSource::Url { url }) => url.clone(),
+        None => {
+            return Err(anyhow!(
+                "Instance {} is not synced from a modpack manifest",
+                instance.config.name
+            ))
+        }
+    };
+    let data = fetch_bytes(client, &url).await?;
+    verify_manifest(&data)?;
+    Manifest::parse(&data)
+}
+
+/// Game version declared by the installed manifest, or the defaults when no
+/// modpack has been installed yet.
+pub fn installed_game_version(meta_dirs: &MetaDirectories) -> anyhow::Result<GameVersion> {
     let manifest_path = meta_dirs.meta.join("manifest.json");
+    if !manifest_path.exists() {
+        return Ok(GameVersion::default());
+    }
+
+    let local = Manifest::parse(&fs::read(&manifest_path)?)?;
+    Ok(local.game)
+}
+
+pub async fn modpack_required_update(instance: Option<&str>) -> anyhow::Result<bool> {
+    let meta_dirs = MetaDirectories::new()?;
+    let instance = Instance::resolve(&meta_dirs, instance)?;
+    let manifest_path = instance.manifest_path(&meta_dirs);
 
     if !manifest_path.exists() {
         Ok(true)
     } else {
-        let client = Client::new();
-        let data = client
-            .get("https://testez4.astralresources.com.br/api/v1/update/minecraft/manifest")
-            .send()
-            .await?
-            .bytes()
-            .await?;
-
-        let remote: Manifest = serde_json::from_slice(&data)?;
+        let client = http_client()?;
+        let remote = fetch_remote_manifest(&client, &meta_dirs, &instance).await?;
         let local_file = fs::read(&manifest_path)?;
         let local: Manifest = serde_json::from_slice(&local_file)?;
+        let selection = OptionalSelection::load(&instance.dir)?;
 
-        Ok(local.version != remote.version)
+        Ok(local.version != remote.version
+            || selection_pending(&local, &selection, &instance.dir))
     }
 }
 
-pub async fn download_modpack(app: AppHandle) -> anyhow::Result<()> {
+/// Whether an optional entry is missing although enabled, or still installed
+/// although the player turned it off since the last sync.
+fn selection_pending(local: &Manifest, selection: &OptionalSelection, instance_dir: &Path) -> bool {
+    local.files.iter().filter(|f| f.optional).any(|file| {
+        let enabled = selection.is_enabled(&file.path);
+        expand_installed(std::iter::once(file)).iter().any(|installed| {
+            let exists = instance_dir.join(&installed.path).exists();
+            (enabled && !exists) || (!enabled && exists && file.policy == FilePolicy::Overwrite)
+        })
+    })
+}
+
+/// Installed manifest, or the remote one when nothing has been installed yet.
+async fn current_manifest(meta_dirs: &MetaDirectories, instance: &Instance) -> anyhow::Result<Manifest> {
+    let manifest_path = instance.manifest_path(meta_dirs);
+    if manifest_path.exists() {
+        Manifest::parse(&fs::read(&manifest_path)?)
+    } else {
+        fetch_remote_manifest(&http_client()?, meta_dirs, instance).await
+    }
+}
+
+pub async fn list_optional_mods(instance: Option<&str>) -> anyhow::Result<Vec<OptionalMod>> {
     let meta_dirs = MetaDirectories::new()?;
-    let manifest_path = meta_dirs.meta.join("manifest.json");
+    let instance = Instance::resolve(&meta_dirs, instance)?;
+    let manifest = current_manifest(&meta_dirs, &instance).await?;
+    let selection = OptionalSelection::load(&instance.dir)?;
+    Ok(manifest.optional_mods(&selection))
+}
+
+/// Records the player's choice for an optional entry; the next sync installs
+/// or removes it.
+pub async fn set_optional_mod(
+    instance: Option<&str>,
+    path: &str,
+    enabled: bool,
+) -> anyhow::Result<Vec<OptionalMod>> {
+    let meta_dirs = MetaDirectories::new()?;
+    let instance = Instance::resolve(&meta_dirs, instance)?;
+    let manifest = current_manifest(&meta_dirs, &instance).await?;
+    let path = normalize_relative(path)?;
+    if !manifest.files.iter().any(|f| f.optional && f.path == path) {
+        return Err(anyhow!("{} is not an optional modpack file", path));
+    }
+
+    let mut selection = OptionalSelection::load(&instance.dir)?;
+    selection.set(&path, enabled);
+    selection.save(&instance.dir)?;
+    Ok(manifest.optional_mods(&selection))
+}
+
+/// What syncing the instance with the remote manifest would change.
+#[derive(Debug, Serialize, Default)]
+pub struct UpdatePlan {
+    pub add: Vec<String>,
+    pub update: Vec<String>,
+    pub remove: Vec<String>,
+    pub keep: Vec<String>,
+    pub preserved: Vec<PreservedFile>,
+    /// Bytes fetched from the network, patches included.
+    #[serde(rename = "downloadBytes")]
+    pub download_bytes: u64,
+    /// Extra disk space the sync needs at its peak: new store blobs, patches
+    /// and files that are copied or extracted rather than hardlinked.
+    #[serde(rename = "requiredBytes")]
+    pub required_bytes: u64,
+}
+
+type ArchiveJob<'a> = (&'a FileEntry, ArchiveFormat, Vec<(String, &'a ArchiveFile)>);
+
+/// The work behind an [`UpdatePlan`], consumed by `download_modpack`.
+struct SyncPlan<'a> {
+    summary: UpdatePlan,
+    changed: Vec<&'a FileEntry>,
+    archive_jobs: Vec<ArchiveJob<'a>>,
+    tasks: Vec<DownloadTask>,
+    patch_jobs: Vec<PatchJob>,
+}
+
+struct InstanceState<'a> {
+    dir: &'a Path,
+    index: &'a mut FileIndex,
+    store: &'a FileStore,
+    full_verify: bool,
+}
+
+/// Why a local file whose hash differs from the manifest is left alone, if it
+/// is. `previous_sha1` is what the last installed manifest put there.
+fn preserve_reason(
+    policy: FilePolicy,
+    local_hash: &str,
+    previous_sha1: Option<&str>,
+) -> Option<PreserveReason> {
+    match policy {
+        FilePolicy::Overwrite => None,
+        FilePolicy::CreateOnly => Some(PreserveReason::AlreadyExists),
+        FilePolicy::KeepIfModified => {
+            let unmodified = previous_sha1.is_some_and(|p| p.eq_ignore_ascii_case(local_hash));
+            (!unmodified).then_some(PreserveReason::Modified)
+        }
+    }
+}
+
+/// Compares the installed files against `remote` without writing anything;
+/// only the in-memory file index is refreshed. `on_checked` receives the
+/// status and path of every entry found to need no work.
+fn plan_sync<'a>(
+    remote: &'a Manifest,
+    local: Option<&Manifest>,
+    selection: &'a OptionalSelection,
+    instance: &mut InstanceState<'_>,
+    on_checked: impl Fn(&str, &str, u64),
+) -> anyhow::Result<SyncPlan<'a>> {
+    let mut plan = SyncPlan {
+        summary: UpdatePlan::default(),
+        changed: Vec::new(),
+        archive_jobs: Vec::new(),
+        tasks: Vec::new(),
+        patch_jobs: Vec::new(),
+    };
+    let previous: HashMap<String, &str> = local
+        .map(|l| l.installed_files().into_iter().map(|f| (f.path, f.sha1)).collect())
+        .unwrap_or_default();
+    let selected = remote.selected_files(selection);
+
+    let remote_paths: HashSet<String> = expand_installed(selected.clone())
+        .into_iter()
+        .map(|f| f.path)
+        .collect();
+    if let Some(local) = local {
+        for old_file in local.installed_files() {
+            if remote_paths.contains(&old_file.path) {
+                continue;
+            }
+
+            let path = instance.dir.join(&old_file.path);
+            let modified = match instance.index.hash(&old_file.path, &path, instance.full_verify)? {
+                Some(hash) => !hash.eq_ignore_ascii_case(old_file.sha1),
+                None => continue,
+            };
+
+            if old_file.policy != FilePolicy::Overwrite && modified {
+                plan.summary.keep.push(old_file.path.clone());
+                plan.summary.preserved.push(PreservedFile {
+                    path: old_file.path,
+                    reason: PreserveReason::RemovedFromPack,
+                });
+            } else {
+                plan.summary.remove.push(old_file.path);
+            }
+        }
+    }
+
+    let mut queued = HashSet::new();
+    for (checked, file) in selected.enumerate() {
+        let mut queue_blob = |plan: &mut SyncPlan<'a>| -> anyhow::Result<Option<DownloadTask>> {
+            if instance.store.is_valid(&file.sha1, file.size)? || !queued.insert(file.sha1.clone()) {
+                return Ok(None);
+            }
+            plan.summary.required_bytes += file.size;
+            Ok(Some(DownloadTask {
+                label: file.path.clone(),
+                url: file.url.clone(),
+                mirrors: remote.mirrors_for(file),
+                target: instance.store.blob_path(&file.sha1)?,
+                size: file.size,
+                sha1: file.sha1.clone(),
+            }))
+        };
+
+        if let Some(archive) = &file.archive {
+            let mut members = Vec::new();
+            for member in &archive.files {
+                let path = format!("{}/{}", file.path, member.path);
+                let target_path = instance.dir.join(&path);
+                match instance.index.hash(&path, &target_path, instance.full_verify)? {
+                    Some(hash) if hash.eq_ignore_ascii_case(&member.sha1) => {
+                        plan.summary.keep.push(path);
+                    }
+                    Some(hash) => {
+                        let previous_sha1 = previous.get(&path).copied();
+                        match preserve_reason(file.policy, &hash, previous_sha1) {
+                            Some(reason) => {
+                                plan.summary.keep.push(path.clone());
+                                plan.summary.preserved.push(PreservedFile { path, reason });
+                            }
+                            None => {
+                                plan.summary.update.push(path.clone());
+                                members.push((path, member));
+                            }
+                        }
+                    }
+                    None => {
+                        plan.summary.add.push(path.clone());
+                        members.push((path, member));
+                    }
+                }
+            }
+
+            if members.is_empty() {
+                on_checked("Up-to-date", &file.path, checked as u64);
+                continue;
+            }
+
+            plan.summary.required_bytes += members.iter().map(|(_, m)| m.size).sum::<u64>();
+            if let Some(task) = queue_blob(&mut plan)? {
+                plan.summary.download_bytes += task.size;
+                plan.tasks.push(task);
+            }
+            plan.archive_jobs.push((file, archive.format, members));
+            continue;
+        }
+
+        let target_path = instance.dir.join(&file.path);
+        let local_hash = instance.index.hash(&file.path, &target_path, instance.full_verify)?;
+        let preserve = match &local_hash {
+            Some(hash) if !hash.eq_ignore_ascii_case(&file.sha1) => {
+                preserve_reason(file.policy, hash, previous.get(&file.path).copied())
+            }
+            _ => None,
+        };
+        let need_download = match &local_hash {
+            Some(hash) => !hash.eq_ignore_ascii_case(&file.sha1) && preserve.is_none(),
+            None => true,
+        };
+
+        if let Some(reason) = preserve {
+            plan.summary.preserved.push(PreservedFile {
+                path: file.path.clone(),
+                reason,
+            });
+        }
+
+        if !need_download {
+            plan.summary.keep.push(file.path.clone());
+            let status = if preserve.is_some() { "Preserved" } else { "Up-to-date" };
+            on_checked(status, &file.path, checked as u64);
+            continue;
+        }
 
-    let client = Client::new();
-    let data = client
-        .get("https://testez4.astralresources.com.br/api/v1/update/minecraft/manifest")
-        .send()
-        .await?
-        .bytes()
-        .await?;
-    let remote: Manifest = serde_json::from_slice(&data)?;
+        match local_hash {
+            Some(_) => plan.summary.update.push(file.path.clone()),
+            None => plan.summary.add.push(file.path.clone()),
+        }
+        if file.policy != FilePolicy::Overwrite || !is_shareable(&file.path) {
+            plan.summary.required_bytes += file.size;
+        }
+        plan.changed.push(file);
+
+        if let Some(task) = queue_blob(&mut plan)? {
+            let patch = local_hash.as_ref().and_then(|hash| {
+                file.patches
+                    .iter()
+                    .find(|p| p.from_sha1.eq_ignore_ascii_case(hash))
+            });
+            match patch {
+                Some(patch) => {
+                    plan.summary.download_bytes += patch.size;
+                    plan.summary.required_bytes += patch.size;
+                    plan.patch_jobs.push(PatchJob {
+                        label: file.path.clone(),
+                        base: target_path,
+                        format: patch.format,
+                        url: patch.url.clone(),
+                        patch_sha1: patch.sha1.clone(),
+                        patch_size: patch.size,
+                        target_sha1: file.sha1.clone(),
+                        target_size: file.size,
+                        fallback: task,
+                    });
+                }
+                None => {
+                    plan.summary.download_bytes += task.size;
+                    plan.tasks.push(task);
+                }
+            }
+        }
+    }
+
+    Ok(plan)
+}
+
+/// Works out what `download_modpack` would do, without downloading or
+/// writing anything.
+pub async fn plan_modpack_update(instance: Option<&str>) -> anyhow::Result<UpdatePlan> {
+    let meta_dirs = MetaDirectories::new()?;
+    let instance = Instance::resolve(&meta_dirs, instance)?;
+    let manifest_path = instance.manifest_path(&meta_dirs);
+
+    let remote = fetch_remote_manifest(&http_client()?, &meta_dirs, &instance).await?;
+    let local = if manifest_path.exists() {
+        Some(Manifest::parse(&fs::read(&manifest_path)?)?)
+    } else {
+        None
+    };
+    let selection = OptionalSelection::load(&instance.dir)?;
+    let store = FileStore::new(&meta_dirs.caches);
+    let mut index = FileIndex::load(&instance.index_path(&meta_dirs));
+
+    let plan = plan_sync(
+        &remote,
+        local.as_ref(),
+        &selection,
+        &mut InstanceState {
+            dir: &instance.dir,
+            index: &mut index,
+            store: &store,
+            full_verify: false,
+        },
+        |_, _, _| {},
+    )?;
+    Ok(plan.summary)
+}
+
+/// Syncs `instance` (or the default instance) with its remote manifest. With
+/// `full_verify` every installed file is rehashed instead of trusting the
+/// cached file index.
+pub async fn download_modpack(
+    app: AppHandle,
+    instance: Option<&str>,
+    mut options: DownloadOptions,
+    full_verify: bool,
+) -> anyhow::Result<SyncReport> {
+    let meta_dirs = MetaDirectories::new()?;
+    let instance = Instance::resolve(&meta_dirs, instance)?;
+    let _lock = InstanceLock::acquire(&meta_dirs, &instance.id, Operation::Update)?;
+    let _store_lock = StoreLock::shared(&meta_dirs)?;
+    options
+        .partial_dir
+        .get_or_insert_with(|| instance.cache_dir(&meta_dirs).join("downloads"));
+    let manifest_path = instance.manifest_path(&meta_dirs);
+    let index_path = instance.index_path(&meta_dirs);
+    if StagedUpdate::recover(&instance.dir, &instance.cache_dir(&meta_dirs), &manifest_path)? {
+        let _ = app.emit("logs", serde_json::json!({
+            "type": "launcher",
+            "message": "[Launcher:Rust] Restored files from an interrupted update",
+        }));
+    }
+
+    let client = http_client()?;
+    let remote = fetch_remote_manifest(&client, &meta_dirs, &instance).await?;
 
     let local: Option<Manifest> = if manifest_path.exists() {
         let local_file = fs::read(&manifest_path)?;
-        Some(serde_json::from_slice(&local_file)?)
+        Some(Manifest::parse(&local_file)?)
     } else {
         None
     };
 
-    let total_files = remote.files.len() as u64;
-    let mut downloaded = 0u64;
+    let selection = OptionalSelection::load(&instance.dir)?;
+    let total_files = remote.selected_files(&selection).count() as u64;
 
     let emit_progress = {
         let app = app.clone();
@@ -90,72 +690,177 @@ pub async fn download_modpack(app: AppHandle) -> anyhow::Result<()> {
         total_files,
     );
 
-    if let Some(local) = &local {
-        for old_file in &local.files {
-            if !remote.files.iter().any(|f| f.path == old_file.path) {
-                let path_to_remove = meta_dirs.default_instance.join(&old_file.path);
-                if path_to_remove.exists() {
-                    fs::remove_file(&path_to_remove)?;
-                }
-            }
-        }
-    }
-
-    for file in &remote.files {
-        let target_path = meta_dirs.default_instance.join(&file.path);
-        let mut need_download = true;
-
-        if target_path.exists() {
-            let mut hasher = Sha1::new();
-            let bytes = fs::read(&target_path)?;
-            hasher.update(&bytes);
-            let hash = format!("{:x}", hasher.finalize());
-            if hash == file.sha1 {
-                need_download = false;
-            }
-        }
-
-        if need_download {
-            emit_progress(
-                &format!("Downloading {}", file.path),
-                (downloaded as f32 / total_files as f32) * 100.0,
-                "downloading",
-                downloaded,
-                total_files,
-            );
-
-            let bytes = client.get(&file.url).send().await?.bytes().await?;
-            if let Some(parent) = target_path.parent() {
-                fs::create_dir_all(parent)?;
-            }
-            fs::write(&target_path, &bytes)?;
+    let store = FileStore::new(&meta_dirs.caches);
+    let mut index = FileIndex::load(&index_path);
+    let plan = plan_sync(
+        &remote,
+        local.as_ref(),
+        &selection,
+        &mut InstanceState {
+            dir: &instance.dir,
+            index: &mut index,
+            store: &store,
+            full_verify,
+        },
+        |status, path, checked| {
             emit_progress(
-                &format!("Installed {}", file.path),
-                (downloaded as f32 / total_files as f32) * 100.0,
+                &format!("{}: {}", status, path),
+                (checked as f32 / total_files as f32) * 100.0,
                 "installing",
-                downloaded,
+                checked,
                 total_files,
             );
-        } else {
+        },
+    )?;
+    index.save(&index_path)?;
+
+    let SyncPlan {
+        summary,
+        changed,
+        archive_jobs,
+        mut tasks,
+        patch_jobs,
+    } = plan;
+    let stale = summary.remove;
+    preflight(&app, &meta_dirs.root_dir, summary.required_bytes)?;
+    let mut report = SyncReport {
+        preserved: summary.preserved,
+        ..SyncReport::default()
+    };
+
+    let update = StagedUpdate::new(instance.dir.clone(), &instance.cache_dir(&meta_dirs))?;
+
+    let on_progress = {
+        let emit_progress = emit_progress.clone();
+        move |path: &str, current: u64, total: u64| {
+            let percentage = if total > 0 {
+                (current as f64 / total as f64 * 100.0) as f32
+            } else {
+                100.0
+            };
             emit_progress(
-                &format!("Up-to-date: {}", file.path),
-                (downloaded as f32 / total_files as f32) * 100.0,
+                &format!("Downloading {}", path),
+                percentage.clamp(0.0, 100.0),
                 "downloading",
-                downloaded,
-                total_files,
+                current,
+                total,
             );
         }
+    };
+
+    if !patch_jobs.is_empty() {
+        emit_progress(
+            &format!("Patching {} files", patch_jobs.len()),
+            0.0,
+            "downloading",
+            0,
+            patch_jobs.len() as u64,
+        );
+        let patch_dir = meta_dirs.caches.join("patches");
+        fs::create_dir_all(&patch_dir)?;
+        let fallbacks = apply_patches(
+            &client,
+            &store,
+            patch_jobs,
+            &patch_dir,
+            &options,
+            on_progress.clone(),
+        )
+        .await;
+        tasks.extend(fallbacks);
+    }
 
-        downloaded += 1;
+    if !tasks.is_empty() {
+        let total_bytes: u64 = tasks.iter().map(|t| t.size).sum();
         emit_progress(
-            &format!("Progress {}", file.path),
-            (downloaded as f32 / total_files as f32) * 100.0,
+            &format!("Downloading {} files", tasks.len()),
+            0.0,
             "downloading",
-            downloaded,
-            total_files,
+            0,
+            total_bytes,
+        );
+
+        match download_all(&client, tasks, &options, on_progress).await {
+            Ok(sources) => report
+                .fallbacks
+                .extend(sources.into_iter().filter(|s| !s.failed.is_empty())),
+            Err(e) => {
+                update.discard();
+                return Err(e);
+            }
+        }
+    }
+
+    for file in &changed {
+        let staged = update.staged_path(&file.path);
+        let populated = match file.policy {
+            FilePolicy::Overwrite => store.install_into(&file.sha1, &file.path, &staged),
+            _ => store.copy_into(&file.sha1, &staged),
+        };
+        if let Err(e) = populated {
+            update.discard();
+            return Err(e);
+        }
+    }
+
+    let mut extracted = Vec::new();
+    for (file, format, job_members) in &archive_jobs {
+        let members: Vec<ArchiveMember> = job_members
+            .iter()
+            .map(|(path, member)| ArchiveMember {
+                name: member.path.clone(),
+                sha1: member.sha1.clone(),
+                size: member.size,
+                output: update.staged_path(path),
+            })
+            .collect();
+        let result = store
+            .blob_path(&file.sha1)
+            .and_then(|blob| extract_members(*format, &blob, &members));
+        if let Err(e) = result {
+            update.discard();
+            return Err(anyhow!("Failed to extract {}: {}", file.path, e));
+        }
+        extracted.extend(
+            job_members
+                .iter()
+                .map(|(path, member)| (path.clone(), member.sha1.clone())),
         );
     }
 
+    emit_progress(
+        "Applying modpack update...",
+        100.0,
+        "installing",
+        total_files,
+        total_files,
+    );
+    let installed: Vec<(String, String)> = changed
+        .iter()
+        .map(|f| (f.path.clone(), f.sha1.clone()))
+        .chain(extracted)
+        .collect();
+    let changed_paths: Vec<String> = installed.iter().map(|(path, _)| path.clone()).collect();
+    update.commit(
+        &changed_paths,
+        &stale,
+        &manifest_path,
+        &serde_json::to_vec_pretty(&remote)?,
+    )?;
+
+    for (path, sha1) in &installed {
+        index.record(path, &instance.dir.join(path), sha1);
+    }
+    for path in &stale {
+        index.forget(path);
+    }
+    index.save(&index_path)?;
+
+    // Settings may have been edited while the update ran.
+    let mut config = Instance::resolve(&meta_dirs, Some(&instance.id))?.config;
+    config.game = remote.game.clone();
+    config.save(&instance.dir)?;
+
     emit_progress(
         "Download complete",
         100.0,
@@ -164,7 +869,138 @@ pub async fn download_modpack(app: AppHandle) -> anyhow::Result<()> {
         total_files,
     );
 
-    fs::write(&manifest_path, serde_json::to_vec_pretty(&remote)?)?;
+    for preserved in &report.preserved {
+        let _ = app.emit("logs", serde_json::json!({
+            "type": "launcher",
+            "message": format!("[Launcher:Rust] Preserved {} ({:?})", preserved.path, preserved.reason),
+        }));
+    }
+
+    for source in &report.fallbacks {
+        let _ = app.emit("logs", serde_json::json!({
+            "type": "launcher",
+            "message": format!(
+                "[Launcher:Rust] {} served by {} after {} failed attempt(s)",
+                source.label,
+                source.served_by,
+                source.failed.len()
+            ),
+        }));
+    }
 
-    Ok(())
+    Ok(report)
+}
+
+/// Removes stored blobs that no installed manifest or staged update references
+/// any more. Refused while any sync or import holds the store.
+pub fn collect_store_garbage() -> anyhow::Result<GcReport> {
+    let meta_dirs = MetaDirectories::new()?;
+    let _store_lock = StoreLock::exclusive(&meta_dirs)?;
+
+    let list = list_instances(&meta_dirs)?;
+    // A broken instance's manifest may still reference blobs in the store.
+    if let Some(broken) = list.broken.first() {
+        return Err(anyhow!(
+            "Cannot clean the file store while instance {} is unreadable: {}",
+            broken.id,
+            broken.error
+        ));
+    }
+
+    let mut referenced = HashSet::new();
+    for instance in list.instances {
+        let manifest_path = instance.manifest_path(&meta_dirs);
+        if manifest_path.exists() {
+            let local: Manifest = serde_json::from_slice(&fs::read(&manifest_path)?)?;
+            referenced.extend(local.files.iter().map(|f| f.sha1.to_ascii_lowercase()));
+        }
+        referenced.extend(StagedUpdate::staged_hashes(&instance.cache_dir(&meta_dirs))?);
+    }
+
+    FileStore::new(&meta_dirs.caches).collect_garbage(&referenced)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sha1::{Digest, Sha1};
+    use std::path::PathBuf;
+
+    fn sha1(data: &str) -> String {
+        format!("{:x}", Sha1::digest(data.as_bytes()))
+    }
+
+    fn temp_instance(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("meta-modpack-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("config/pack")).unwrap();
+        dir
+    }
+
+    fn archive_manifest(policy: &str, members: &[(&str, &str)]) -> Manifest {
+        let files: Vec<_> = members
+            .iter()
+            .map(|(path, content)| serde_json::json!({"path": path, "sha1": sha1(content), "size": content.len()}))
+            .collect();
+        let manifest = serde_json::json!({
+            "version": "1",
+            "files": [{
+                "path": "config/pack",
+                "sha1": "0000000000000000000000000000000000000000",
+                "size": 1,
+                "url": "https://example.invalid/pack.zip",
+                "policy": policy,
+                "archive": { "format": "zip", "files": files },
+            }],
+        });
+        Manifest::parse(&serde_json::to_vec(&manifest).unwrap()).unwrap()
+    }
+
+    fn plan(dir: &Path, remote: &Manifest, local: &Manifest) -> UpdatePlan {
+        let mut index = FileIndex::default();
+        let store = FileStore::new(&dir.join("caches"));
+        let selection = OptionalSelection::default();
+        let mut state = InstanceState {
+            dir,
+            index: &mut index,
+            store: &store,
+            full_verify: true,
+        };
+        plan_sync(remote, Some(local), &selection, &mut state, |_, _, _| {})
+            .unwrap()
+            .summary
+    }
+
+    #[test]
+    fn keep_if_modified_archive_preserves_edited_members() {
+        let dir = temp_instance("keep");
+        fs::write(dir.join("config/pack/edited.cfg"), "mine").unwrap();
+        fs::write(dir.join("config/pack/untouched.cfg"), "old").unwrap();
+
+        let local = archive_manifest("keep-if-modified", &[("edited.cfg", "old"), ("untouched.cfg", "old")]);
+        let remote = archive_manifest("keep-if-modified", &[("edited.cfg", "new"), ("untouched.cfg", "new")]);
+        let summary = plan(&dir, &remote, &local);
+
+        assert_eq!(summary.update, vec!["config/pack/untouched.cfg"]);
+        assert_eq!(summary.keep, vec!["config/pack/edited.cfg"]);
+        assert_eq!(summary.preserved.len(), 1);
+        assert_eq!(summary.preserved[0].path, "config/pack/edited.cfg");
+        assert!(matches!(summary.preserved[0].reason, PreserveReason::Modified));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn create_only_archive_leaves_existing_members() {
+        let dir = temp_instance("create");
+        fs::write(dir.join("config/pack/existing.cfg"), "mine").unwrap();
+
+        let local = archive_manifest("create-only", &[]);
+        let remote = archive_manifest("create-only", &[("existing.cfg", "new"), ("missing.cfg", "new")]);
+        let summary = plan(&dir, &remote, &local);
+
+        assert_eq!(summary.add, vec!["config/pack/missing.cfg"]);
+        assert!(summary.update.is_empty());
+        assert!(matches!(summary.preserved[0].reason, PreserveReason::AlreadyExists));
+        let _ = fs::remove_dir_all(&dir);
+    }
 }
@@ -0,0 +1,139 @@
+use crate::import::{extract_overrides, install_files, PackFile};
+use crate::instance::{new_instance_dir, InstanceConfig};
+use crate::meta::MetaDirectories;
+use crate::modpack::GameVersion;
+use crate::paths::normalize_relative;
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::AppHandle;
+use tauri::Emitter;
+
+#[derive(Deserialize)]
+struct MrpackIndex {
+    name: String,
+    files: Vec<MrpackFile>,
+    dependencies: HashMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct MrpackFile {
+    path: String,
+    hashes: HashMap<String, String>,
+    #[serde(default)]
+    env: Option<MrpackEnv>,
+    downloads: Vec<String>,
+    #[serde(rename = "fileSize")]
+    file_size: u64,
+}
+
+#[derive(Deserialize)]
+struct MrpackEnv {
+    client: String,
+}
+
+/// Imports a Modrinth `.mrpack` archive as a new instance and returns its directory.
+pub async fn import_mrpack(
+    app: AppHandle,
+    archive_path: PathBuf,
+    name: Option<String>,
+) -> Result<PathBuf> {
+    let meta_dirs = MetaDirectories::new()?;
+    meta_dirs.ensure()?;
+
+    let mut archive = zip::ZipArchive::new(fs::File::open(&archive_path)?)?;
+    let index: MrpackIndex = {
+        let entry = archive
+            .by_name("modrinth.index.json")
+            .map_err(|_| anyhow!("Not a Modrinth modpack: modrinth.index.json is missing"))?;
+        serde_json::from_reader(entry)?
+    };
+    let game = game_version(&index.dependencies)?;
+
+    let name = name.unwrap_or_else(|| index.name.clone());
+    let instance_dir = new_instance_dir(&meta_dirs, &name)?;
+
+    if let Err(e) = install(&app, &meta_dirs, &mut archive, &index, &instance_dir).await {
+        let _ = fs::remove_dir_all(&instance_dir);
+        return Err(e);
+    }
+
+    InstanceConfig::new(name, game).save(&instance_dir)?;
+    Ok(instance_dir)
+}
+
+async fn install(
+    app: &AppHandle,
+    meta_dirs: &MetaDirectories,
+    archive: &mut zip::ZipArchive<fs::File>,
+    index: &MrpackIndex,
+    instance_dir: &Path,
+) -> Result<()> {
+    let mut files = Vec::new();
+    for file in &index.files {
+        if file.env.as_ref().is_some_and(|env| env.client == "unsupported") {
+            continue;
+        }
+
+        let path = normalize_relative(&file.path)?;
+        let sha1 = file
+            .hashes
+            .get("sha1")
+            .ok_or_else(|| anyhow!("{} has no sha1 hash", path))?
+            .to_ascii_lowercase();
+        let (url, mirrors) = file
+            .downloads
+            .split_first()
+            .ok_or_else(|| anyhow!("{} has no download URL", path))?;
+
+        files.push(PackFile {
+            path,
+            sha1,
+            size: file.file_size,
+            url: url.clone(),
+            mirrors: mirrors.to_vec(),
+        });
+    }
+
+    install_files(app, meta_dirs, &files, instance_dir).await?;
+
+    let _ = app.emit(
+        "minecraft-progress",
+        serde_json::json!({
+            "message": "Applying overrides...",
+            "percentage": 100.0,
+            "component": "installing",
+            "current": 0,
+            "total": 0,
+        }),
+    );
+    extract_overrides(archive, "overrides/", instance_dir)?;
+    extract_overrides(archive, "client-overrides/", instance_dir)?;
+    Ok(())
+}
+
+fn game_version(dependencies: &HashMap<String, String>) -> Result<GameVersion> {
+    let minecraft_version = dependencies
+        .get("minecraft")
+        .ok_or_else(|| anyhow!("Modpack does not declare a Minecraft version"))?
+        .clone();
+
+    let (loader, loader_version) = [
+        ("forge", "forge"),
+        ("neoforge", "neoforge"),
+        ("fabric-loader", "fabric"),
+        ("quilt-loader", "quilt"),
+    ]
+    .iter()
+    .find_map(|(key, loader)| dependencies.get(*key).map(|v| (loader.to_string(), v.clone())))
+    .ok_or_else(|| anyhow!("Modpack does not declare a supported mod loader"))?;
+
+    Ok(GameVersion {
+        minecraft_version,
+        loader,
+        loader_version,
+        jvm_args: Vec::new(),
+    })
+}
@@ -0,0 +1,51 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Player choices for the optional entries of a modpack, persisted as
+/// `optional-mods.json` in the instance. Entries without a choice are enabled.
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct OptionalSelection {
+    pub enabled: BTreeMap<String, bool>,
+}
+
+/// An optional manifest entry as presented to the player.
+#[derive(Debug, Serialize, Clone)]
+pub struct OptionalMod {
+    pub path: String,
+    pub group: Option<String>,
+    pub description: Option<String>,
+    pub size: u64,
+    pub enabled: bool,
+}
+
+impl OptionalSelection {
+    pub fn load(instance_dir: &Path) -> Result<Self> {
+        let path = selection_path(instance_dir);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        Ok(serde_json::from_slice(&fs::read(&path)?)?)
+    }
+
+    pub fn save(&self, instance_dir: &Path) -> Result<()> {
+        fs::create_dir_all(instance_dir)?;
+        fs::write(selection_path(instance_dir), serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn is_enabled(&self, path: &str) -> bool {
+        self.enabled.get(path).copied().unwrap_or(true)
+    }
+
+    pub fn set(&mut self, path: &str, enabled: bool) {
+        self.enabled.insert(path.to_string(), enabled);
+    }
+}
+
+fn selection_path(instance_dir: &Path) -> PathBuf {
+    instance_dir.join("optional-mods.json")
+}
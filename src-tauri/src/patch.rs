@@ -0,0 +1,110 @@
+use crate::download::{download_all, move_into_place, DownloadOptions, DownloadTask};
+use crate::store::{copy_hashed, FileStore};
+use anyhow::{anyhow, Result};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{BufReader, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum PatchFormat {
+    /// A zstd frame compressed with `--patch-from=<old file>`.
+    #[default]
+    Zstd,
+}
+
+/// A delta from a file already on disk to the content a manifest entry expects.
+pub struct PatchJob {
+    pub label: String,
+    pub base: PathBuf,
+    pub format: PatchFormat,
+    pub url: String,
+    pub patch_sha1: String,
+    pub patch_size: u64,
+    pub target_sha1: String,
+    pub target_size: u64,
+    /// Full download used when the patch cannot be fetched or applied.
+    pub fallback: DownloadTask,
+}
+
+/// Downloads and applies every patch into the store, returning the full
+/// downloads still needed for the jobs that could not be patched.
+pub async fn apply_patches<F>(
+    client: &Client,
+    store: &FileStore,
+    jobs: Vec<PatchJob>,
+    patch_dir: &Path,
+    options: &DownloadOptions,
+    on_progress: F,
+) -> Vec<DownloadTask>
+where
+    F: Fn(&str, u64, u64) + Send + Sync + 'static,
+{
+    let tasks = jobs
+        .iter()
+        .map(|job| DownloadTask {
+            label: format!("{} (patch)", job.label),
+            url: job.url.clone(),
+            mirrors: Vec::new(),
+            target: patch_dir.join(format!("{}.patch", job.patch_sha1)),
+            size: job.patch_size,
+            sha1: job.patch_sha1.clone(),
+        })
+        .collect::<Vec<_>>();
+    let patch_paths: Vec<PathBuf> = tasks.iter().map(|t| t.target.clone()).collect();
+
+    if download_all(client, tasks, options, on_progress).await.is_err() {
+        return jobs.into_iter().map(|job| job.fallback).collect();
+    }
+
+    let mut fallbacks = Vec::new();
+    for (job, patch_path) in jobs.into_iter().zip(patch_paths) {
+        let applied = store
+            .blob_path(&job.target_sha1)
+            .and_then(|blob| apply(&job, &patch_path, &blob));
+        let _ = fs::remove_file(&patch_path);
+        if applied.is_err() {
+            fallbacks.push(job.fallback);
+        }
+    }
+    fallbacks
+}
+
+fn apply(job: &PatchJob, patch_path: &Path, blob: &Path) -> Result<()> {
+    let base = fs::read(&job.base)?;
+    let temp_path = patch_path.with_extension("out");
+
+    let result = decode_into(job, &base, patch_path, &temp_path);
+    if let Err(e) = result {
+        let _ = fs::remove_file(&temp_path);
+        return Err(e);
+    }
+
+    if let Some(parent) = blob.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    move_into_place(&temp_path, blob)?;
+    Ok(())
+}
+
+fn decode_into(job: &PatchJob, base: &[u8], patch_path: &Path, output: &Path) -> Result<()> {
+    let mut decoder = match job.format {
+        PatchFormat::Zstd => {
+            let patch = BufReader::new(fs::File::open(patch_path)?);
+            let mut decoder = zstd::stream::read::Decoder::with_ref_prefix(patch, base)?;
+            decoder.window_log_max(31)?;
+            decoder
+        }
+    };
+
+    let mut file = fs::File::create(output)?;
+    let (hash, size) = copy_hashed(&mut decoder, |chunk| file.write_all(chunk))?;
+    file.sync_all()?;
+
+    if size != job.target_size || !hash.eq_ignore_ascii_case(&job.target_sha1) {
+        return Err(anyhow!("Patched {} does not match the manifest", job.label));
+    }
+    Ok(())
+}
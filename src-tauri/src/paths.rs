@@ -0,0 +1,105 @@
+use anyhow::{anyhow, Result};
+use std::path::{Path, PathBuf};
+
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Normalizes a manifest path into a `/`-separated path relative to the
+/// instance, rejecting anything that could resolve outside of it.
+///
+/// Backslashes are treated as separators on every platform so a manifest
+/// cannot smuggle Windows traversal past a Unix build, and `.` segments and
+/// repeated separators are dropped.
+pub fn normalize_relative(path: &str) -> Result<String> {
+    if path.contains('\0') {
+        return Err(anyhow!("Invalid path in manifest: {:?}", path));
+    }
+
+    let unified = path.replace('\\', "/");
+    if unified.starts_with('/') {
+        return Err(anyhow!("Absolute path in manifest: {}", path));
+    }
+
+    let mut parts = Vec::new();
+    for part in unified.split('/') {
+        match part {
+            "" | "." => continue,
+            ".." => return Err(anyhow!("Path escapes the instance: {}", path)),
+            _ => {}
+        }
+
+        if part.contains(':') {
+            return Err(anyhow!("Drive or stream specifier in manifest path: {}", path));
+        }
+        if part.ends_with('.') || part.ends_with(' ') {
+            return Err(anyhow!("Invalid path segment in manifest: {}", path));
+        }
+        let stem = part.split('.').next().unwrap_or(part);
+        if RESERVED_NAMES.iter().any(|r| r.eq_ignore_ascii_case(stem)) {
+            return Err(anyhow!("Reserved file name in manifest path: {}", path));
+        }
+
+        parts.push(part);
+    }
+
+    if parts.is_empty() {
+        return Err(anyhow!("Empty path in manifest"));
+    }
+
+    Ok(parts.join("/"))
+}
+
+/// Joins a manifest path onto `base` after validating it.
+pub fn safe_join(base: &Path, path: &str) -> Result<PathBuf> {
+    Ok(base.join(normalize_relative(path)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_and_normalizes_plain_paths() {
+        assert_eq!(normalize_relative("mods/a.jar").unwrap(), "mods/a.jar");
+        assert_eq!(normalize_relative("./mods//a.jar").unwrap(), "mods/a.jar");
+        assert_eq!(normalize_relative("config\\forge.toml").unwrap(), "config/forge.toml");
+        assert_eq!(normalize_relative("mods/..a.jar").unwrap(), "mods/..a.jar");
+    }
+
+    #[test]
+    fn rejects_parent_traversal() {
+        assert!(normalize_relative("../outside.jar").is_err());
+        assert!(normalize_relative("mods/../../outside.jar").is_err());
+        assert!(normalize_relative("mods\\..\\..\\outside.jar").is_err());
+        assert!(normalize_relative("mods/..").is_err());
+    }
+
+    #[test]
+    fn rejects_absolute_paths() {
+        assert!(normalize_relative("/etc/passwd").is_err());
+        assert!(normalize_relative("\\Windows\\System32\\evil.dll").is_err());
+        assert!(normalize_relative("\\\\server\\share\\evil.jar").is_err());
+        assert!(normalize_relative("C:\\Windows\\evil.dll").is_err());
+        assert!(normalize_relative("C:evil.dll").is_err());
+    }
+
+    #[test]
+    fn rejects_empty_and_special_names() {
+        assert!(normalize_relative("").is_err());
+        assert!(normalize_relative("./").is_err());
+        assert!(normalize_relative("mods/a.jar\0").is_err());
+        assert!(normalize_relative("mods/CON.jar").is_err());
+        assert!(normalize_relative("mods/a.jar.").is_err());
+        assert!(normalize_relative("mods/a.jar:stream").is_err());
+    }
+
+    #[test]
+    fn safe_join_stays_under_base() {
+        let base = Path::new("instances").join("MetaInstance");
+        let joined = safe_join(&base, "mods/./a.jar").unwrap();
+        assert!(joined.starts_with(&base));
+        assert!(safe_join(&base, "../MetaInstance2/mods/a.jar").is_err());
+    }
+}
@@ -0,0 +1,164 @@
+use anyhow::{anyhow, Result};
+use lyceris::auth::AuthMethod;
+use lyceris::json::version::meta::vanilla::{Arguments, Element, Value, VersionMeta};
+use lyceris::minecraft::config::{Config, Memory};
+use lyceris::minecraft::loader::Loader;
+use lyceris::minecraft::parse::ParseRule;
+use lyceris::minecraft::CLASSPATH_SEPARATOR;
+use sha1::{Digest, Sha1};
+use std::collections::HashMap;
+use std::path::Path;
+use tokio::process::Command;
+
+/// Builds the game command the way lyceris' `launch` does, so the caller can
+/// set the environment and spawn it. `java` replaces the runtime lyceris
+/// installed for the version.
+pub async fn game_command<T: Loader>(config: &Config<T>, java: Option<&Path>) -> Result<Command> {
+    let raw = tokio::fs::read(config.get_version_json_path()).await?;
+    let mut meta: VersionMeta = serde_json::from_slice(&raw)?;
+    let profile_dir = config.profile.clone().map(|p| p.root.join(p.name));
+    let current_dir = profile_dir.unwrap_or_else(|| config.game_dir.clone());
+
+    let meta_arguments = meta.arguments.take().unwrap_or_else(|| Arguments {
+        game: meta
+            .minecraft_arguments
+            .clone()
+            .unwrap_or_default()
+            .split_whitespace()
+            .map(|argument| Element::String(argument.to_string()))
+            .collect(),
+        jvm: vec![
+            Element::String("-Djava.library.path=${natives_directory}".to_string()),
+            Element::String("-cp".to_string()),
+            Element::String("${classpath}".to_string()),
+        ],
+    });
+
+    let mut variables = HashMap::<&'static str, String>::new();
+    match &config.authentication {
+        AuthMethod::Microsoft {
+            username,
+            xuid,
+            uuid,
+            access_token,
+            ..
+        } => {
+            variables.insert("${auth_player_name}", username.clone());
+            variables.insert("${auth_xuid}", xuid.clone());
+            variables.insert("${auth_uuid}", uuid.clone());
+            variables.insert("${auth_access_token}", access_token.clone());
+            variables.insert("${user_type}", "msa".to_string());
+        }
+        AuthMethod::Offline { username, uuid } => {
+            let uuid = uuid.clone().unwrap_or_else(|| offline_uuid(username));
+            variables.insert("${auth_player_name}", username.clone());
+            variables.insert("${auth_xuid}", uuid.clone());
+            variables.insert("${auth_uuid}", uuid);
+            variables.insert("${auth_access_token}", "token".to_string());
+            variables.insert("${user_type}", "mojang".to_string());
+        }
+    }
+    // The official launcher's client id, as lyceris sends it.
+    variables.insert("${clientid}", "00000000402b5328".to_string());
+    variables.insert("${user_properties}", String::new());
+    variables.insert("${launcher_name}", env!("CARGO_PKG_NAME").to_string());
+    variables.insert("${launcher_version}", env!("CARGO_PKG_VERSION").to_string());
+    variables.insert("${version_name}", config.get_version_name());
+    variables.insert("${game_directory}", current_dir.to_string_lossy().into_owned());
+
+    let assets_dir = config.get_assets_path();
+    variables.insert("${assets_root}", assets_dir.to_string_lossy().into_owned());
+    variables.insert(
+        "${game_assets}",
+        assets_dir.join("virtual").join("legacy").to_string_lossy().into_owned(),
+    );
+    variables.insert("${assets_index_name}", meta.asset_index.id.clone());
+    variables.insert("${version_type}", meta.r#type.clone());
+    variables.insert(
+        "${natives_directory}",
+        config
+            .get_natives_path()
+            .join(&config.version)
+            .to_string_lossy()
+            .into_owned(),
+    );
+
+    let libraries_path = config.get_libraries_path();
+    let mut classpath: Vec<String> = meta
+        .libraries
+        .iter()
+        .filter(|lib| !lib.skip_args && lib.rules.parse_rule() && lib.natives.is_none())
+        .filter_map(|lib| lib.downloads.as_ref()?.artifact.as_ref()?.path.as_ref())
+        .map(|path| libraries_path.join(path).to_string_lossy().into_owned())
+        .collect();
+    classpath.push(config.get_version_jar_path().to_string_lossy().into_owned());
+    variables.insert("${classpath}", classpath.join(CLASSPATH_SEPARATOR));
+    variables.insert("${library_directory}", libraries_path.to_string_lossy().into_owned());
+    variables.insert("${classpath_separator}", CLASSPATH_SEPARATOR.to_string());
+
+    let expand = |arg: &str| -> String {
+        variables
+            .iter()
+            .fold(arg.to_string(), |arg, (key, value)| arg.replace(key, value))
+    };
+
+    let mut arguments = vec![match &config.memory {
+        Some(Memory::Gigabyte(m)) => format!("-Xmx{}G", m),
+        Some(Memory::Megabyte(m)) => format!("-Xmx{}M", m),
+        None => "-Xmx2G".to_string(),
+    }];
+    for arg in &meta_arguments.jvm {
+        match arg {
+            Element::String(arg) => arguments.push(expand(arg)),
+            Element::Class(class) if class.rules.parse_rule() => match &class.value {
+                Value::Single(arg) => arguments.push(expand(arg)),
+                Value::Multiple(args) => arguments.extend(args.iter().map(|arg| expand(arg))),
+            },
+            Element::Class(_) => {}
+        }
+    }
+    arguments.extend(config.custom_java_args.iter().map(|arg| expand(arg)));
+    arguments.push(meta.main_class.clone());
+    for arg in &meta_arguments.game {
+        if let Element::String(arg) = arg {
+            arguments.push(expand(arg));
+        }
+    }
+    arguments.extend(config.custom_args.iter().map(|arg| expand(arg)));
+
+    let java = match java {
+        Some(java) => {
+            if !java.is_file() {
+                return Err(anyhow!("Java executable not found: {}", java.display()));
+            }
+            java.to_path_buf()
+        }
+        None => config
+            .get_java_path(&meta.java_version.take().unwrap_or_default())
+            .await?,
+    };
+
+    tokio::fs::create_dir_all(&current_dir).await?;
+    let mut command = Command::new(java);
+    command.args(arguments).current_dir(current_dir);
+    Ok(command)
+}
+
+/// Name-based UUID for offline players, so the same name keeps its
+/// playerdata between launches.
+fn offline_uuid(username: &str) -> String {
+    let hash = Sha1::digest(format!("OfflinePlayer:{}", username).as_bytes());
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&hash[..16]);
+    bytes[6] = (bytes[6] & 0x0f) | 0x50;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
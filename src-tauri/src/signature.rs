@@ -0,0 +1,124 @@
+use anyhow::{anyhow, Result};
+use base64::Engine;
+use minisign_verify::{PublicKey, Signature};
+use serde_json::{Map, Value};
+
+/// Minisign public key modpack manifests are signed with, base64-encoded like
+/// the updater `pubkey` in `tauri.conf.json`. It is a separate key from the
+/// updater's, so the modpack server never holds the key that signs releases.
+/// See `docs/manifest-signing.md` for producing signatures.
+const MANIFEST_PUBKEY: &str = "dW50cnVzdGVkIGNvbW1lbnQ6IG1pbmlzaWduIHB1YmxpYyBrZXk6IDkzMkEzREI1NTE4NTY2NDAKUldSQVpvVlJ0VDBxazFjeFlDd1JWV2NJM0N6NUVtVnVjVnQxcnlGaXlvMjY1SjFiSjl5MVZHN0wK";
+
+/// Debug builds trust this key instead when it is set, so local test
+/// manifests can be signed with the key in `testdata/`.
+#[cfg(debug_assertions)]
+const PUBKEY_OVERRIDE_VAR: &str = "META_MANIFEST_PUBKEY";
+
+/// Checks the detached `signature` carried by a raw manifest against the
+/// launcher's manifest key.
+pub fn verify_manifest(raw: &[u8]) -> Result<()> {
+    #[cfg(debug_assertions)]
+    if let Ok(encoded) = std::env::var(PUBKEY_OVERRIDE_VAR) {
+        return verify_manifest_with(raw, &decode_public_key(&encoded)?);
+    }
+    verify_manifest_with(raw, &decode_public_key(MANIFEST_PUBKEY)?)
+}
+
+/// Checks the detached `signature` carried by a raw manifest against `public_key`.
+///
+/// The signed message is the manifest without its `signature` field, serialized
+/// as compact JSON with object keys sorted (see [`signing_payload`]).
+pub fn verify_manifest_with(raw: &[u8], public_key: &PublicKey) -> Result<()> {
+    let mut manifest: Value = serde_json::from_slice(raw)?;
+    let signature = manifest
+        .as_object_mut()
+        .and_then(|obj| obj.remove("signature"))
+        .and_then(|sig| sig.as_str().map(str::to_string))
+        .ok_or_else(|| anyhow!("Modpack manifest is not signed"))?;
+
+    let signature = Signature::decode(&decode_base64(&signature)?)
+        .map_err(|e| anyhow!("Invalid manifest signature: {}", e))?;
+
+    public_key
+        .verify(&signing_payload(manifest)?, &signature, true)
+        .map_err(|e| anyhow!("Modpack manifest signature verification failed: {}", e))
+}
+
+/// Canonical bytes a manifest signature covers, for publishers producing one.
+pub fn signing_payload(manifest: Value) -> Result<Vec<u8>> {
+    Ok(serde_json::to_vec(&canonicalize(manifest))?)
+}
+
+fn canonicalize(value: Value) -> Value {
+    match value {
+        Value::Object(obj) => {
+            let mut entries: Vec<_> = obj.into_iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+            let mut sorted = Map::new();
+            for (key, value) in entries {
+                sorted.insert(key, canonicalize(value));
+            }
+            Value::Object(sorted)
+        }
+        Value::Array(items) => Value::Array(items.into_iter().map(canonicalize).collect()),
+        other => other,
+    }
+}
+
+/// Decodes a public key in the base64-wrapped form of [`MANIFEST_PUBKEY`].
+pub fn decode_public_key(encoded: &str) -> Result<PublicKey> {
+    PublicKey::decode(&decode_base64(encoded)?).map_err(|e| anyhow!("Invalid manifest public key: {}", e))
+}
+
+fn decode_base64(encoded: &str) -> Result<String> {
+    let bytes = base64::engine::general_purpose::STANDARD.decode(encoded.trim())?;
+    Ok(String::from_utf8(bytes)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_PUBKEY: &str = include_str!("../testdata/manifest-test.pub");
+    const SIGNED: &str = include_str!("../testdata/manifest.signed.json");
+
+    fn test_key() -> PublicKey {
+        PublicKey::decode(TEST_PUBKEY).unwrap()
+    }
+
+    fn signed_manifest() -> Value {
+        serde_json::from_str(SIGNED).unwrap()
+    }
+
+    #[test]
+    fn accepts_signed_manifest() {
+        verify_manifest_with(SIGNED.as_bytes(), &test_key()).unwrap();
+
+        // Key order and whitespace are not part of the signed payload.
+        let compact = serde_json::to_vec(&signed_manifest()).unwrap();
+        verify_manifest_with(&compact, &test_key()).unwrap();
+    }
+
+    #[test]
+    fn rejects_tampered_manifest() {
+        let mut manifest = signed_manifest();
+        manifest["files"][0]["url"] = "https://attacker.invalid/jei.jar".into();
+        let raw = serde_json::to_vec(&manifest).unwrap();
+        assert!(verify_manifest_with(&raw, &test_key()).is_err());
+    }
+
+    #[test]
+    fn rejects_unsigned_manifest() {
+        let mut manifest = signed_manifest();
+        manifest.as_object_mut().unwrap().remove("signature");
+        let raw = serde_json::to_vec(&manifest).unwrap();
+        let err = verify_manifest_with(&raw, &test_key()).unwrap_err();
+        assert_eq!(err.to_string(), "Modpack manifest is not signed");
+    }
+
+    #[test]
+    fn rejects_manifest_signed_with_another_key() {
+        assert!(verify_manifest(SIGNED.as_bytes()).is_err());
+        decode_public_key(MANIFEST_PUBKEY).unwrap();
+    }
+}
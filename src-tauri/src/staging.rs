@@ -0,0 +1,378 @@
+use crate::download::move_into_place;
+use crate::store::sha1_file;
+use anyhow::{anyhow, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+enum Applied {
+    Backed { original: PathBuf, backup: PathBuf },
+    Installed(PathBuf),
+}
+
+/// A modpack update assembled under `caches/staging` and swapped into the
+/// instance only once every file is present.
+///
+/// Files that get replaced or removed are first moved to `caches/backup`, so
+/// a failure while swapping puts the instance and manifest back as they were.
+/// A `pending` marker and a journal of the files the update adds survive a
+/// crash mid-swap, and [`StagedUpdate::recover`] finishes the rollback on the
+/// next run.
+pub struct StagedUpdate {
+    instance: PathBuf,
+    staging: PathBuf,
+    backup: PathBuf,
+}
+
+/// Written at the start of a commit; holds "1" if a manifest was installed before.
+const PENDING: &str = "pending";
+/// Files the update adds that did not exist before, one relative path per line.
+const CREATED: &str = "created";
+
+impl StagedUpdate {
+    /// Puts back whatever an interrupted commit moved out of the instance.
+    /// Returns whether there was anything to recover. On error the backup is
+    /// left in place so nothing is lost.
+    pub fn recover(instance: &Path, caches: &Path, manifest_path: &Path) -> Result<bool> {
+        let backup = caches.join("backup");
+        let marker = backup.join(PENDING);
+        if !marker.exists() {
+            return Ok(false);
+        }
+
+        let had_manifest = fs::read_to_string(&marker)?.trim() == "1";
+        let mut errors = Vec::new();
+        let created = backup.join(CREATED);
+        if created.exists() {
+            for relative in fs::read_to_string(&created)?.lines().filter(|l| !l.is_empty()) {
+                let target = instance.join(relative);
+                match fs::remove_file(&target) {
+                    Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                        errors.push(format!("{}: {}", relative, e));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        let files = backup.join("files");
+        if files.exists() {
+            for relative in files_under(&files)? {
+                let target = instance.join(&relative);
+                if let Err(e) = restore(&files.join(&relative), &target) {
+                    errors.push(format!("{}: {}", relative.display(), e));
+                }
+            }
+        }
+
+        let backed_manifest = backup.join("manifest.json");
+        let manifest_result = if backed_manifest.exists() {
+            restore(&backed_manifest, manifest_path)
+        } else if !had_manifest && manifest_path.exists() {
+            fs::remove_file(manifest_path)
+        } else {
+            Ok(())
+        };
+        if let Err(e) = manifest_result {
+            errors.push(format!("{}: {}", manifest_path.display(), e));
+        }
+
+        if !errors.is_empty() {
+            return Err(anyhow!("Could not recover interrupted update: {}", errors.join("; ")));
+        }
+        fs::remove_dir_all(&backup)?;
+        Ok(true)
+    }
+
+    pub fn new(instance: PathBuf, caches: &Path) -> Result<Self> {
+        let staging = caches.join("staging");
+        let backup = caches.join("backup");
+        if backup.join(PENDING).exists() {
+            return Err(anyhow!("An interrupted update has not been recovered yet"));
+        }
+        for dir in [&staging, &backup] {
+            if dir.exists() {
+                fs::remove_dir_all(dir)?;
+            }
+            fs::create_dir_all(dir)?;
+        }
+
+        Ok(Self {
+            instance,
+            staging,
+            backup,
+        })
+    }
+
+    /// Hashes of whatever an unfinished update left staged under `caches`.
+    pub fn staged_hashes(caches: &Path) -> Result<Vec<String>> {
+        let files = caches.join("staging").join("files");
+        if !files.exists() {
+            return Ok(Vec::new());
+        }
+        files_under(&files)?
+            .iter()
+            .map(|relative| Ok(sha1_file(&files.join(relative))?))
+            .collect()
+    }
+
+    pub fn staged_path(&self, relative: &str) -> PathBuf {
+        self.staging.join("files").join(relative)
+    }
+
+    /// Replaces `replace` with their staged copies, removes `remove`, and writes
+    /// the new manifest, rolling everything back if any step fails.
+    pub fn commit(
+        self,
+        replace: &[String],
+        remove: &[String],
+        manifest_path: &Path,
+        manifest: &[u8],
+    ) -> Result<()> {
+        let staged_manifest = self.staging.join("manifest.json");
+        fs::write(&staged_manifest, manifest)?;
+        self.begin(replace, manifest_path)?;
+
+        let mut applied = Vec::new();
+        let result = self.apply(replace, remove, manifest_path, &staged_manifest, &mut applied);
+
+        if let Err(e) = result {
+            // A failed rollback keeps the backup and its marker for `recover`.
+            return match rollback(applied) {
+                Ok(()) => {
+                    self.discard();
+                    Err(anyhow!("Modpack update failed and was rolled back: {}", e))
+                }
+                Err(rollback_err) => Err(anyhow!(
+                    "Modpack update failed ({}) and rollback failed: {}",
+                    e,
+                    rollback_err
+                )),
+            };
+        }
+
+        // The update is in place; without the marker the backup is just garbage.
+        fs::remove_file(self.backup.join(PENDING))?;
+        self.discard();
+        Ok(())
+    }
+
+    /// Drops everything staged so far without touching the instance.
+    pub fn discard(self) {
+        let _ = fs::remove_dir_all(&self.staging);
+        let _ = fs::remove_dir_all(&self.backup);
+    }
+
+    /// Journals the files this update adds and sets the marker, so a crash
+    /// from here on is undone by `recover`.
+    fn begin(&self, replace: &[String], manifest_path: &Path) -> Result<()> {
+        let created: Vec<&str> = replace
+            .iter()
+            .filter(|relative| !self.instance.join(relative).exists())
+            .map(String::as_str)
+            .collect();
+        fs::write(self.backup.join(CREATED), created.join("\n"))?;
+        let had_manifest = if manifest_path.exists() { "1" } else { "0" };
+        fs::write(self.backup.join(PENDING), had_manifest)?;
+        Ok(())
+    }
+
+    fn apply(
+        &self,
+        replace: &[String],
+        remove: &[String],
+        manifest_path: &Path,
+        staged_manifest: &Path,
+        applied: &mut Vec<Applied>,
+    ) -> Result<()> {
+        for relative in remove {
+            let target = self.instance.join(relative);
+            backup(&target, &self.backup.join("files").join(relative), applied)?;
+        }
+
+        for relative in replace {
+            let target = self.instance.join(relative);
+            backup(&target, &self.backup.join("files").join(relative), applied)?;
+            install(&self.staged_path(relative), &target, applied)?;
+        }
+
+        backup(manifest_path, &self.backup.join("manifest.json"), applied)?;
+        install(staged_manifest, manifest_path, applied)?;
+        Ok(())
+    }
+}
+
+fn backup(original: &Path, backup: &Path, applied: &mut Vec<Applied>) -> Result<()> {
+    if !original.exists() {
+        return Ok(());
+    }
+
+    if let Some(parent) = backup.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    move_into_place(original, backup)?;
+    applied.push(Applied::Backed {
+        original: original.to_path_buf(),
+        backup: backup.to_path_buf(),
+    });
+    Ok(())
+}
+
+fn install(staged: &Path, target: &Path, applied: &mut Vec<Applied>) -> Result<()> {
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    move_into_place(staged, target)?;
+    applied.push(Applied::Installed(target.to_path_buf()));
+    Ok(())
+}
+
+/// Undoes every applied step, carrying on past failures so one stuck file
+/// does not leave the rest un-restored.
+fn rollback(applied: Vec<Applied>) -> Result<()> {
+    let mut errors = Vec::new();
+    for step in applied.into_iter().rev() {
+        let (path, result) = match step {
+            Applied::Installed(path) => {
+                let result = fs::remove_file(&path);
+                (path, result)
+            }
+            Applied::Backed { original, backup } => {
+                let result = move_into_place(&backup, &original);
+                (original, result)
+            }
+        };
+        if let Err(e) = result {
+            errors.push(format!("{}: {}", path.display(), e));
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(anyhow!(errors.join("; ")));
+    }
+    Ok(())
+}
+
+fn restore(backup: &Path, original: &Path) -> std::io::Result<()> {
+    if let Some(parent) = original.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    move_into_place(backup, original)
+}
+
+/// Every file below `root`, relative to it.
+fn files_under(root: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                pending.push(path);
+            } else {
+                files.push(path.strip_prefix(root)?.to_path_buf());
+            }
+        }
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Fixture {
+        root: PathBuf,
+        instance: PathBuf,
+        caches: PathBuf,
+        manifest: PathBuf,
+    }
+
+    /// An instance with `mods/jei-1.0.jar` installed by manifest "old", and an
+    /// update to `mods/jei-1.1.jar` staged.
+    fn fixture(name: &str) -> (Fixture, StagedUpdate) {
+        let root = std::env::temp_dir().join(format!("meta-staging-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let fixture = Fixture {
+            instance: root.join("instance"),
+            caches: root.join("caches"),
+            manifest: root.join("manifest.json"),
+            root,
+        };
+        fs::create_dir_all(fixture.instance.join("mods")).unwrap();
+        fs::write(fixture.instance.join("mods/jei-1.0.jar"), "old jar").unwrap();
+        fs::write(&fixture.manifest, "old").unwrap();
+
+        let update = StagedUpdate::new(fixture.instance.clone(), &fixture.caches).unwrap();
+        let staged = update.staged_path("mods/jei-1.1.jar");
+        fs::create_dir_all(staged.parent().unwrap()).unwrap();
+        fs::write(staged, "new jar").unwrap();
+        (fixture, update)
+    }
+
+    fn strings(paths: &[&str]) -> Vec<String> {
+        paths.iter().map(|p| p.to_string()).collect()
+    }
+
+    fn assert_old_state(fixture: &Fixture) {
+        let mods = fixture.instance.join("mods");
+        assert_eq!(fs::read_to_string(mods.join("jei-1.0.jar")).unwrap(), "old jar");
+        assert!(!mods.join("jei-1.1.jar").exists());
+        assert_eq!(fs::read_to_string(&fixture.manifest).unwrap(), "old");
+    }
+
+    #[test]
+    fn failure_mid_apply_rolls_back() {
+        let (fixture, update) = fixture("apply");
+        // The second file was never staged, so installing it fails after the
+        // renamed jar is already in place.
+        let replace = strings(&["mods/jei-1.1.jar", "mods/missing.jar"]);
+        let result = update.commit(&replace, &strings(&["mods/jei-1.0.jar"]), &fixture.manifest, b"new");
+
+        assert!(result.is_err());
+        assert_old_state(&fixture);
+        assert!(!fixture.instance.join("mods/missing.jar").exists());
+        assert!(!StagedUpdate::recover(&fixture.instance, &fixture.caches, &fixture.manifest).unwrap());
+        fs::remove_dir_all(&fixture.root).unwrap();
+    }
+
+    #[test]
+    fn recover_undoes_interrupted_commit() {
+        let (fixture, update) = fixture("recover");
+        let replace = strings(&["mods/jei-1.1.jar"]);
+        let remove = strings(&["mods/jei-1.0.jar"]);
+        let staged_manifest = update.staging.join("manifest.json");
+        fs::write(&staged_manifest, "new").unwrap();
+
+        // Crash after every step was applied but before the marker was cleared.
+        update.begin(&replace, &fixture.manifest).unwrap();
+        update
+            .apply(&replace, &remove, &fixture.manifest, &staged_manifest, &mut Vec::new())
+            .unwrap();
+        assert!(StagedUpdate::new(fixture.instance.clone(), &fixture.caches).is_err());
+
+        assert!(StagedUpdate::recover(&fixture.instance, &fixture.caches, &fixture.manifest).unwrap());
+        assert_old_state(&fixture);
+        assert!(!fixture.caches.join("backup").exists());
+        assert!(StagedUpdate::new(fixture.instance.clone(), &fixture.caches).is_ok());
+        fs::remove_dir_all(&fixture.root).unwrap();
+    }
+
+    #[test]
+    fn recover_removes_manifest_of_first_install() {
+        let (fixture, update) = fixture("first");
+        fs::remove_file(&fixture.manifest).unwrap();
+        let replace = strings(&["mods/jei-1.1.jar"]);
+        let staged_manifest = update.staging.join("manifest.json");
+        fs::write(&staged_manifest, "new").unwrap();
+
+        update.begin(&replace, &fixture.manifest).unwrap();
+        update
+            .apply(&replace, &[], &fixture.manifest, &staged_manifest, &mut Vec::new())
+            .unwrap();
+
+        assert!(StagedUpdate::recover(&fixture.instance, &fixture.caches, &fixture.manifest).unwrap());
+        assert!(!fixture.manifest.exists());
+        assert!(!fixture.instance.join("mods/jei-1.1.jar").exists());
+        assert!(fixture.instance.join("mods/jei-1.0.jar").exists());
+        fs::remove_dir_all(&fixture.root).unwrap();
+    }
+}
@@ -0,0 +1,180 @@
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use sha1::{Digest, Sha1};
+use std::collections::HashSet;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Content-addressed blobs under `caches/store`, keyed by sha1. Files the
+/// game only reads are shared by every instance through hardlinks; see
+/// [`is_shareable`].
+pub struct FileStore {
+    root: PathBuf,
+}
+
+#[derive(Debug, Serialize, Default)]
+pub struct GcReport {
+    pub removed: u64,
+    #[serde(rename = "freedBytes")]
+    pub freed_bytes: u64,
+}
+
+impl FileStore {
+    pub fn new(caches: &Path) -> Self {
+        Self {
+            root: caches.join("store"),
+        }
+    }
+
+    pub fn blob_path(&self, sha1: &str) -> Result<PathBuf> {
+        if sha1.len() != 40 || !sha1.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(anyhow!("Invalid sha1 in manifest: {}", sha1));
+        }
+        let sha1 = sha1.to_ascii_lowercase();
+        Ok(self.root.join(&sha1[..2]).join(sha1))
+    }
+
+    /// Whether an intact blob for `sha1` is already stored. A blob that no
+    /// longer matches its hash (e.g. edited through a hardlink) is evicted.
+    pub fn has_valid(&self, sha1: &str, size: u64) -> Result<bool> {
+        if self.is_valid(sha1, size)? {
+            return Ok(true);
+        }
+
+        let path = self.blob_path(sha1)?;
+        if path.exists() {
+            fs::remove_file(&path)?;
+        }
+        Ok(false)
+    }
+
+    /// Like [`FileStore::has_valid`], but leaves a damaged blob in place.
+    pub fn is_valid(&self, sha1: &str, size: u64) -> Result<bool> {
+        let path = self.blob_path(sha1)?;
+        let Ok(meta) = fs::metadata(&path) else {
+            return Ok(false);
+        };
+
+        Ok(meta.len() == size && sha1_file(&path)?.eq_ignore_ascii_case(sha1))
+    }
+
+    /// Populates `target` from the blob, hardlinking when `relative` (its path
+    /// in the instance) [`is_shareable`] and copying otherwise.
+    pub fn install_into(&self, sha1: &str, relative: &str, target: &Path) -> Result<()> {
+        self.populate(sha1, target, is_shareable(relative))
+    }
+
+    /// Populates `target` with a private copy, for files players are expected
+    /// to edit in place (which would otherwise rewrite the shared blob).
+    pub fn copy_into(&self, sha1: &str, target: &Path) -> Result<()> {
+        self.populate(sha1, target, false)
+    }
+
+    fn populate(&self, sha1: &str, target: &Path, hardlink: bool) -> Result<()> {
+        let blob = self.blob_path(sha1)?;
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        if target.exists() {
+            fs::remove_file(target)?;
+        }
+
+        if !hardlink || fs::hard_link(&blob, target).is_err() {
+            fs::copy(&blob, target)?;
+        }
+        Ok(())
+    }
+
+    /// Deletes every blob whose hash is not in `referenced`.
+    pub fn collect_garbage(&self, referenced: &HashSet<String>) -> Result<GcReport> {
+        let mut report = GcReport::default();
+        if !self.root.exists() {
+            return Ok(report);
+        }
+
+        for shard in fs::read_dir(&self.root)? {
+            let shard = shard?.path();
+            if !shard.is_dir() {
+                continue;
+            }
+
+            for blob in fs::read_dir(&shard)? {
+                let blob = blob?;
+                let name = blob.file_name().to_string_lossy().to_ascii_lowercase();
+                if referenced.contains(&name) {
+                    continue;
+                }
+
+                report.freed_bytes += blob.metadata()?.len();
+                report.removed += 1;
+                fs::remove_file(blob.path())?;
+            }
+
+            if fs::read_dir(&shard)?.next().is_none() {
+                fs::remove_dir(&shard)?;
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+/// Folders whose archives the game loads but never writes to.
+const SHAREABLE_DIRS: [&str; 3] = ["mods", "resourcepacks", "shaderpacks"];
+
+/// Whether the file at `relative` can be hardlinked to the shared blob.
+/// Anything else, configs above all, is rewritten in place by the game or
+/// mods and would change the blob for every instance linked to it.
+pub fn is_shareable(relative: &str) -> bool {
+    let path = Path::new(relative);
+    let in_shared_dir = path
+        .components()
+        .next()
+        .is_some_and(|first| SHAREABLE_DIRS.iter().any(|dir| first.as_os_str() == *dir));
+    let archive = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("jar") || ext.eq_ignore_ascii_case("zip"));
+    in_shared_dir && archive
+}
+
+pub fn sha1_file(path: &Path) -> std::io::Result<String> {
+    Ok(copy_hashed(fs::File::open(path)?, |_| Ok(()))?.0)
+}
+
+/// Streams `reader` into `sink` chunk by chunk, returning the sha1 and size of
+/// everything that passed through.
+pub fn copy_hashed<R: Read>(
+    mut reader: R,
+    mut sink: impl FnMut(&[u8]) -> std::io::Result<()>,
+) -> std::io::Result<(String, u64)> {
+    let mut hasher = Sha1::new();
+    let mut size = 0u64;
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        sink(&buf[..n])?;
+        hasher.update(&buf[..n]);
+        size += n as u64;
+    }
+    Ok((format!("{:x}", hasher.finalize()), size))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_archives_the_game_reads_are_shareable() {
+        for path in ["mods/jei.jar", "resourcepacks/Faithful.ZIP", "shaderpacks/bsl.zip"] {
+            assert!(is_shareable(path), "{}", path);
+        }
+        for path in ["config/jei.toml", "options.txt", "config/mods/a.jar", "mods/settings.json", "mods.jar"] {
+            assert!(!is_shareable(path), "{}", path);
+        }
+    }
+}